@@ -0,0 +1,43 @@
+//! Deserialization of the `Bundle`s that every read on the Java service goes through.  Run on a
+//! device with `cargo bench --features bench`, the fixtures are regenerated by
+//! `benches/fixtures/generate.py`.
+
+use android_powerstats::bench::deserialize_bundle;
+use binder::binder_impl::Parcel;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const FIXTURES: &[(&str, &[u8])] = &[
+    ("monitors", include_bytes!("fixtures/monitors.bin")),
+    ("readings", include_bytes!("fixtures/readings.bin")),
+    (
+        "readings_large",
+        include_bytes!("fixtures/readings_large.bin"),
+    ),
+];
+
+/// Parcel with the contents of a fixture, which are all made up of 4-byte words
+fn parcel(bytes: &[u8]) -> Parcel {
+    let mut parcel = Parcel::new();
+    for word in bytes.chunks_exact(4) {
+        let word = u32::from_le_bytes(word.try_into().unwrap());
+        parcel.write(&word).unwrap();
+    }
+    parcel
+}
+
+fn bundle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize_bundle");
+    for &(name, bytes) in FIXTURES {
+        let parcel = parcel(bytes);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                unsafe { parcel.set_data_position(0) }.unwrap();
+                black_box(deserialize_bundle(parcel.borrowed_ref()).unwrap())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bundle);
+criterion_main!(benches);
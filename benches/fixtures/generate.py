@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+"""Writes the `Bundle` fixtures for `benches/bundle.rs`, laid out exactly like the `powerstats`
+service parcels them into `IResultReceiver.send()`: a non-null marker for `writeTypedObject()`
+followed by `Bundle.writeToParcel()`.  Rail names resemble those of recent Pixels."""
+
+import struct
+from pathlib import Path
+
+VAL_PARCELABLEARRAY = 16
+VAL_LONGARRAY = 19
+BUNDLE_MAGIC = 0x4C444E42
+
+RAILS = [
+    ("S2M_VDD_CPUCL2", "CPU(BIG)"),
+    ("S3M_VDD_CPUCL1", "CPU(MID)"),
+    ("S4M_VDD_CPUCL0", "CPU(LITTLE)"),
+    ("S5M_VDD_INT", "INT"),
+    ("S1M_VDD_MIF", "MIF"),
+    ("S9S_VDD_G3D", "GPU"),
+    ("S2S_VDD_G3D_L2", "GPU"),
+    ("S8S_VDD_G3D_L2", "GPU"),
+    ("L2S_VDD_AOC_RET", "AOC"),
+    ("S9S_VDD_AOC", "AOC"),
+    ("S5S_VDDQ_MEM", "MEM"),
+    ("S10S_VDD2L", "DDR"),
+    ("S4S_VDD2H_MEM", "DDR"),
+    ("VSYS_PWR_DISPLAY", "Display"),
+    ("VSYS_PWR_MODEM", "Modem"),
+    ("VSYS_PWR_RFFE", "Cellular"),
+    ("S1S_VDD_CAM", "Camera"),
+    ("S3S_LLDO1", "LDO"),
+]
+CONSUMERS = ["CPU/0", "CPU/1", "CPU/2", "GPU", "DISPLAY/0", "MOBILE_RADIO", "WIFI", "GNSS"]
+
+
+def i32(v):
+    return struct.pack("<i", v)
+
+
+def i64(v):
+    return struct.pack("<q", v)
+
+
+def pad(b):
+    return b + b"\0" * (-len(b) % 4)
+
+
+def string16(s):
+    return i32(len(s)) + pad((s + "\0").encode("utf-16-le"))
+
+
+def string8(s):
+    b = s.encode()
+    return i32(len(b)) + pad(b + b"\0")
+
+
+def parcelable_array(monitors):
+    value = i32(len(monitors))
+    for index, type, name in monitors:
+        value += string16("android.os.PowerMonitor") + i32(index) + i32(type) + string8(name)
+    # Length-prefixed
+    return i32(VAL_PARCELABLEARRAY) + i32(len(value)) + value
+
+
+def long_array(values):
+    return i32(VAL_LONGARRAY) + i32(len(values)) + b"".join(map(i64, values))
+
+
+def bundle(entries):
+    data = i32(len(entries)) + b"".join(string16(k) + v for k, v in entries)
+    # The length doesn't include the magic
+    return i32(1) + i32(len(data)) + i32(BUNDLE_MAGIC) + data
+
+
+def readings(n):
+    timestamps = [123_456_789 + (i % 3) for i in range(n)]
+    energy = [1_000_000 * i + 12_345 for i in range(n)]
+    return bundle([("timestamps", long_array(timestamps)), ("energy", long_array(energy))])
+
+
+monitors = [(i, 0, name) for i, name in enumerate(CONSUMERS)]
+monitors += [
+    (len(CONSUMERS) + i, 1, f"[{rail}]:{subsystem}") for i, (rail, subsystem) in enumerate(RAILS)
+]
+
+out = Path(__file__).parent
+(out / "monitors.bin").write_bytes(bundle([("monitors", parcelable_array(monitors))]))
+(out / "readings.bin").write_bytes(readings(len(RAILS)))
+# Far more monitors than any device has, to see how the array paths scale
+(out / "readings_large.bin").write_bytes(readings(512))
@@ -0,0 +1,85 @@
+//! Peak heap usage of discovering and reading every meter eagerly, against
+//! [`PowerStats::iter_meter_readings()`] which only holds on to one batch of readings at a time.
+//! Allocations are counted by a global allocator, and the HAL is an in-process stand-in with
+//! several hundred rails.  Discovery is part of both paths, so the difference is in the readings
+//! (and their pairing with meters) alone.  Run on a device with `cargo bench --features bench`.
+//!
+//! [`PowerStats::iter_meter_readings()`]: android_powerstats::PowerStats::iter_meter_readings
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use android_powerstats::{bench::vendor_power_stats, PowerStats};
+
+/// Forwards to [`System`] while tracking the number of bytes in use, and the most since the last
+/// [`Counting::reset_peak()`]
+struct Counting {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Counting {
+    fn reset_peak(&self) -> usize {
+        let current = self.current.load(Ordering::Relaxed);
+        self.peak.store(current, Ordering::Relaxed);
+        current
+    }
+
+    /// Most bytes in use since `baseline` was returned by [`Self::reset_peak()`]
+    fn peak_since(&self, baseline: usize) -> usize {
+        self.peak.load(Ordering::Relaxed) - baseline
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+fn eager(stats: &PowerStats) {
+    let meters = stats.energy_meters().unwrap();
+    let ids = meters.iter().map(|m| m.id).collect::<Vec<_>>();
+    let readings = stats.read_energy_meters(&ids).unwrap();
+    for pair in meters.into_iter().zip(readings) {
+        black_box(pair);
+    }
+}
+
+fn streaming(stats: &PowerStats) {
+    for pair in stats.iter_meter_readings().unwrap() {
+        black_box(pair.unwrap());
+    }
+}
+
+fn main() {
+    println!("{:>6} {:>14} {:>14}", "rails", "eager (B)", "streaming (B)");
+    for rails in [200, 500, 1000] {
+        let stats = vendor_power_stats(rails);
+        let peak = |read: fn(&PowerStats)| {
+            let baseline = ALLOCATOR.reset_peak();
+            read(&stats);
+            ALLOCATOR.peak_since(baseline)
+        };
+        println!("{rails:>6} {:>14} {:>14}", peak(eager), peak(streaming));
+    }
+}
@@ -0,0 +1,22 @@
+//! A 1-id read on the Java service with the pooled `ResultReceiver`, against one that creates a
+//! new receiver like every read did before.  The service is an in-process stand-in that answers
+//! immediately, so this only measures the per-read setup that pooling saves rather than the round
+//! trip to `system_server`.  Run on a device with `cargo bench --features bench`.
+
+use android_powerstats::bench::{java_power_stats, read_energy_meters_fresh_receiver};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn read(c: &mut Criterion) {
+    let stats = java_power_stats();
+    let mut group = c.benchmark_group("read_energy_meters_java");
+    group.bench_function("pooled_receiver", |b| {
+        b.iter(|| black_box(stats.read_energy_meters(&[0]).unwrap()))
+    });
+    group.bench_function("fresh_receiver", |b| {
+        b.iter(|| black_box(read_energy_meters_fresh_receiver(&stats, &[0]).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, read);
+criterion_main!(benches);
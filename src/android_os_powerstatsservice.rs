@@ -1,19 +1,27 @@
-use std::sync::{
-    mpsc::{channel, Receiver, Sender},
-    OnceLock,
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        OnceLock,
+    },
+    time::Instant,
 };
 
+use anyhow::Context;
+
 use binder::{
     binder_impl::{BorrowedParcel, Deserialize},
     Parcelable, StatusCode,
 };
+use log::warn;
 
 use crate::{
     bundle::{
         parcel_read_string8, register_creator, Bundle, Object, ParcelableCreator,
         ParcelableInstance,
     },
+    permission_denied,
     result_receiver::{IResultReceiver, ResultReceiver},
+    PowerStatsError,
 };
 
 #[path = "android/os/IPowerStatsService.rs"]
@@ -50,9 +58,9 @@ impl Deserialize for PowerMonitor {
         Ok(Self {
             index: parcel.read()?,
             r#type: match parcel.read::<i32>()? {
-                x if x == PowerMonitorType::Consumer as i32 => PowerMonitorType::Consumer,
-                x if x == PowerMonitorType::Measurement as i32 => PowerMonitorType::Measurement,
-                x => todo!("Unknown PowerMonitorType {x:?}"),
+                0 => PowerMonitorType::Consumer,
+                1 => PowerMonitorType::Measurement,
+                x => PowerMonitorType::Unknown(x),
             },
             name: parcel_read_string8(parcel)?,
         })
@@ -61,7 +69,6 @@ impl Deserialize for PowerMonitor {
 // impl_deserialize_for_parcelable!(PowerMonitor);
 
 /// <https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/PowerMonitor.java;l=42-67;drc=d68742df4e3c723ea5296c743606362cd04180bb>
-#[repr(i32)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub(crate) enum PowerMonitorType {
     /**
@@ -72,16 +79,25 @@ pub(crate) enum PowerMonitorType {
      * Some consumer names are standardized, others are not.
      */
     #[default]
-    Consumer = 0,
+    Consumer,
 
     /**
      * Power monitor corresponding to a directly measured power rail. Rails are device-specific:
      * no assumptions can be made about the source of those measurements across different devices,
      * even if they have the same name.
      */
-    Measurement = 1,
+    Measurement,
+
+    /// Added by a newer framework, these monitors are left out of discovery
+    Unknown(i32),
 }
 
+/// Unlike AIDL-generated parcelables, `PowerMonitor.writeToParcel()` doesn't write a size
+/// header in front of its fields: `writeParcelableArray()` only puts the creator name in front of
+/// every element.  Fields appended by a newer framework therefore can't be skipped here, they'd
+/// be misread as the next element instead.  Such a layout is caught by the length prefix of the
+/// enclosing `VAL_PARCELABLEARRAY` and rejected with [`StatusCode::BAD_VALUE`], and needs to be
+/// supported explicitly in [`PowerMonitor::deserialize()`].
 struct PowerMonitorCreator;
 impl ParcelableCreator for PowerMonitorCreator {
     fn create_from_parcel(
@@ -92,32 +108,106 @@ impl ParcelableCreator for PowerMonitorCreator {
     }
 }
 
-pub(crate) struct ReceiveSupportedPowerMonitors(Sender<Vec<PowerMonitor>>);
-impl ReceiveSupportedPowerMonitors {
-    pub(crate) fn new() -> (Self, Receiver<Vec<PowerMonitor>>) {
-        static CREATOR: OnceLock<()> = OnceLock::new();
-        CREATOR.get_or_init(|| register_creator("android.os.PowerMonitor", &PowerMonitorCreator));
+/// Makes `PowerMonitor`s in a [`Bundle`] deserializable, once
+pub(crate) fn register_power_monitor_creator() {
+    static CREATOR: OnceLock<()> = OnceLock::new();
+    CREATOR.get_or_init(|| register_creator("android.os.PowerMonitor", &PowerMonitorCreator));
+}
+
+/// Result delivered to a [`ResultReceiver`], where a nonzero `resultCode` signals an error
+pub(crate) type ReceiverResult<T> = Result<T, PowerStatsError>;
+
+/// Where a [`ResultReceiver`] delivers its result to
+enum ResultSender<T> {
+    Channel(Sender<ReceiverResult<T>>),
+    /// Taken by the first result, later ones are dropped
+    #[cfg(feature = "tokio")]
+    Oneshot(std::sync::Mutex<Option<tokio::sync::oneshot::Sender<ReceiverResult<T>>>>),
+}
+
+impl<T> ResultSender<T> {
+    fn send(&self, result: ReceiverResult<T>) {
+        // The reader may have given up on this result already
+        match self {
+            Self::Channel(s) => {
+                let _ = s.send(result);
+            }
+            #[cfg(feature = "tokio")]
+            Self::Oneshot(s) => {
+                if let Some(s) = s.lock().unwrap().take() {
+                    let _ = s.send(result);
+                }
+            }
+        }
+    }
+
+    fn channel() -> (Self, Receiver<ReceiverResult<T>>) {
         let (s, r) = channel();
+        (Self::Channel(s), r)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn oneshot() -> (Self, tokio::sync::oneshot::Receiver<ReceiverResult<T>>) {
+        let (s, r) = tokio::sync::oneshot::channel();
+        (Self::Oneshot(std::sync::Mutex::new(Some(s))), r)
+    }
+}
+
+pub(crate) struct ReceiveSupportedPowerMonitors(ResultSender<Vec<PowerMonitor>>);
+impl ReceiveSupportedPowerMonitors {
+    pub(crate) fn new() -> (Self, Receiver<ReceiverResult<Vec<PowerMonitor>>>) {
+        register_power_monitor_creator();
+        let (s, r) = ResultSender::channel();
+        (Self(s), r)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn new_oneshot() -> (
+        Self,
+        tokio::sync::oneshot::Receiver<ReceiverResult<Vec<PowerMonitor>>>,
+    ) {
+        register_power_monitor_creator();
+        let (s, r) = ResultSender::oneshot();
         (Self(s), r)
     }
 }
 impl binder::Interface for ReceiveSupportedPowerMonitors {}
 impl IResultReceiver for ReceiveSupportedPowerMonitors {
     fn r#send(&self, code: i32, data: &Bundle) -> binder::Result<()> {
-        assert_eq!(code, 0);
-        let Object::ParcelableArray(monitors) = &data.0[powerstatsservice::KEY_MONITORS] else {
-            panic!("Must have ParcelableArray")
+        if code != 0 {
+            self.0
+                .send(Err(PowerStatsError::ServiceReturnedError(code)));
+            return Ok(());
+        }
+        // Panicking on the callback thread would leave the reader waiting for this result forever
+        let malformed = PowerStatsError::MalformedBundle {
+            key: powerstatsservice::KEY_MONITORS,
+        };
+        let Some(Object::ParcelableArray(monitors)) = data.0.get(powerstatsservice::KEY_MONITORS)
+        else {
+            warn!(
+                "`{}` must have ParcelableArray",
+                powerstatsservice::KEY_MONITORS
+            );
+            self.0.send(Err(malformed));
+            return Ok(());
         };
 
         let result = monitors
             .iter()
             .map(|monitor| {
-                let monitor: &PowerMonitor = monitor.as_any().downcast_ref().unwrap();
-                monitor.clone()
+                monitor
+                    .as_any()
+                    .downcast_ref::<PowerMonitor>()
+                    .cloned()
+                    .ok_or_else(|| {
+                        warn!("Expected a PowerMonitor, got {monitor:?}");
+                        malformed.clone()
+                    })
             })
-            .collect::<Vec<_>>();
+            .collect();
 
-        self.0.send(result).unwrap();
+        self.0.send(result);
 
         Ok(())
     }
@@ -131,30 +221,53 @@ pub(crate) struct PowerMonitorReadings {
     pub(crate) energy_uws: Vec<i64>,
 }
 
-pub(crate) struct ReceivePowerMonitorReadings(Sender<PowerMonitorReadings>);
+pub(crate) struct ReceivePowerMonitorReadings(ResultSender<PowerMonitorReadings>);
 impl ReceivePowerMonitorReadings {
-    pub(crate) fn new() -> (Self, Receiver<PowerMonitorReadings>) {
-        let (s, r) = channel();
+    pub(crate) fn new() -> (Self, Receiver<ReceiverResult<PowerMonitorReadings>>) {
+        let (s, r) = ResultSender::channel();
+        (Self(s), r)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn new_oneshot() -> (
+        Self,
+        tokio::sync::oneshot::Receiver<ReceiverResult<PowerMonitorReadings>>,
+    ) {
+        let (s, r) = ResultSender::oneshot();
         (Self(s), r)
     }
 }
 impl binder::Interface for ReceivePowerMonitorReadings {}
 impl IResultReceiver for ReceivePowerMonitorReadings {
     fn r#send(&self, code: i32, data: &Bundle) -> binder::Result<()> {
-        assert_eq!(code, 0);
-        let Object::LongArray(timestamps) = &data.0[powerstatsservice::KEY_TIMESTAMPS] else {
-            panic!("Must have LongArray")
+        if code != 0 {
+            self.0
+                .send(Err(PowerStatsError::ServiceReturnedError(code)));
+            return Ok(());
+        }
+        // See `ReceiveSupportedPowerMonitors::send()`
+        let long_array = |key| match data.0.get(key) {
+            Some(Object::LongArray(array)) => Ok(array),
+            _ => {
+                warn!("`{key}` must have LongArray");
+                Err(PowerStatsError::MalformedBundle { key })
+            }
         };
-        let Object::LongArray(energy) = &data.0[powerstatsservice::KEY_ENERGY] else {
-            panic!("Must have LongArray")
+        let (timestamps, energy) = match (
+            long_array(powerstatsservice::KEY_TIMESTAMPS),
+            long_array(powerstatsservice::KEY_ENERGY),
+        ) {
+            (Ok(timestamps), Ok(energy)) => (timestamps, energy),
+            (Err(e), _) | (_, Err(e)) => {
+                self.0.send(Err(e));
+                return Ok(());
+            }
         };
 
-        self.0
-            .send(PowerMonitorReadings {
-                timestamps_ms: timestamps.clone(),
-                energy_uws: energy.clone(),
-            })
-            .unwrap();
+        self.0.send(Ok(PowerMonitorReadings {
+            timestamps_ms: timestamps.clone(),
+            energy_uws: energy.clone(),
+        }));
 
         Ok(())
     }
@@ -162,12 +275,57 @@ impl IResultReceiver for ReceivePowerMonitorReadings {
 
 impl dyn IPowerStatsService {
     // Only allowed when having a trait object
-    pub fn receive_supported_power_monitors(&self) -> binder::Result<Vec<PowerMonitor>> {
+    /// Fails with [`PowerStatsError::Timeout`] if the monitors didn't arrive by `deadline`, or
+    /// blocks until they do without one
+    pub fn receive_supported_power_monitors(
+        &self,
+        deadline: Option<Instant>,
+    ) -> anyhow::Result<Vec<PowerMonitor>> {
         let (receiver, chan) = ReceiveSupportedPowerMonitors::new();
         let receiver = ResultReceiver::new(receiver);
         // TODO: Since we pass a borrow, can we get access to the contents again?
-        self.getSupportedPowerMonitors(&receiver)?;
-        let monitors = chan.recv().unwrap();
+        self.getSupportedPowerMonitors(&receiver)
+            .map_err(permission_denied("getSupportedPowerMonitors"))?;
+        // Only the service holds on to the receiver while it's pending, so that the channel
+        // disconnects when the service drops it without calling back
+        drop(receiver);
+        let monitors = match deadline {
+            Some(deadline) => {
+                match chan.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(monitors) => monitors,
+                    Err(RecvTimeoutError::Timeout) => return Err(PowerStatsError::Timeout.into()),
+                    Err(e) => return Err(e).context(DROPPED_MONITORS),
+                }
+            }
+            None => chan.recv().context(DROPPED_MONITORS)?,
+        }?;
+        warn_unknown_monitors(&monitors);
         Ok(monitors)
     }
+
+    /// Like [`Self::receive_supported_power_monitors()`], without blocking a thread on the result
+    #[cfg(feature = "tokio")]
+    pub async fn receive_supported_power_monitors_async(
+        &self,
+    ) -> anyhow::Result<Vec<PowerMonitor>> {
+        let (receiver, chan) = ReceiveSupportedPowerMonitors::new_oneshot();
+        let receiver = ResultReceiver::new(receiver);
+        self.getSupportedPowerMonitors(&receiver)
+            .map_err(permission_denied("getSupportedPowerMonitors"))?;
+        // See `receive_supported_power_monitors()`
+        drop(receiver);
+        let monitors = chan.await.context(DROPPED_MONITORS)??;
+        warn_unknown_monitors(&monitors);
+        Ok(monitors)
+    }
+}
+
+const DROPPED_MONITORS: &str = "ResultReceiver was dropped without delivering power monitors";
+
+fn warn_unknown_monitors(monitors: &[PowerMonitor]) {
+    for pm in monitors {
+        if let PowerMonitorType::Unknown(t) = pm.r#type {
+            warn!("Ignoring power monitor {:?} of unknown type {t}", pm.name);
+        }
+    }
 }
@@ -0,0 +1,143 @@
+//! Last-resort backend for devices that have neither the power stats HAL nor the `powerstats`
+//! service, built on the estimates that the `batterystats` service prints in its `--checkin` dump.
+//! See [`crate::BackendSelection::BatteryStats`] for its limitations.
+
+use std::{io::Read, os::unix::net::UnixStream, thread, time::Duration};
+
+use anyhow::{Context, Result};
+use binder::{binder_impl::IBinderInternal, SpIBinder, StatusCode};
+
+use crate::{EnergyConsumerAttribution, EnergyConsumerReading};
+
+/// Id of the only consumer on [`crate::BackendSelection::BatteryStats`]
+pub const BATTERY_STATS_CONSUMER_ID: i32 = 0;
+
+/// Voltage at which `batterystats` charge estimates are converted to energy, the nominal voltage
+/// of a typical Li-ion cell
+pub const NOMINAL_BATTERY_VOLTAGE: f64 = 3.7;
+
+/// `BATTERY_STATS_CHECKIN_VERSION` that leads every line [`parse_checkin()`] understands
+/// <https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/BatteryStats.java>
+const CHECKIN_VERSION: &str = "9";
+
+pub(crate) fn get_service() -> Result<SpIBinder> {
+    binder::get_service("batterystats")
+        .ok_or(StatusCode::NAME_NOT_FOUND)
+        .context("Failed to get `batterystats` service")
+}
+
+/// Dumps `batterystats --checkin` and reads it as a single consumer reading
+pub(crate) fn read(service: &SpIBinder) -> Result<EnergyConsumerReading> {
+    let (mut reader, writer) = UnixStream::pair()?;
+    // The service writes synchronously, and would block forever on a full socket buffer when the
+    // dump isn't consumed at the same time
+    let dump = thread::spawn(move || {
+        let mut dump = String::new();
+        reader.read_to_string(&mut dump).map(|_| dump)
+    });
+    service.clone().dump(&writer, &["--checkin"])?;
+    drop(writer);
+    let dump = dump.join().unwrap()?;
+
+    // Same CLOCK_BOOTTIME as the timestamps of the other backends
+    let uptime = std::fs::read_to_string("/proc/uptime")?;
+    let uptime = uptime
+        .split_whitespace()
+        .next()
+        .and_then(|s| s.parse().ok())
+        .context("Malformed `/proc/uptime`")?;
+    Ok(parse_checkin(&dump, Duration::from_secs_f64(uptime)))
+}
+
+fn mah_to_uws(mah: f64) -> i64 {
+    // 1mAh is 3.6C, and C*V is J
+    (mah * 3.6 * NOMINAL_BATTERY_VOLTAGE * 1_000_000.0).round() as i64
+}
+
+/// Maps the power use items (`pwi`) of a checkin dump to a reading, with the computed drain of the
+/// power use summary (`pws`) as total (or the sum of all items, without one).  App (`uid`) items
+/// become the attribution, everything else (screen, idle, radios, ...) only counts towards the
+/// total.  Lines of unknown versions or sections are skipped.
+///
+/// <https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/BatteryUsageStats.java>
+pub(crate) fn parse_checkin(dump: &str, timestamp: Duration) -> EnergyConsumerReading {
+    let mut total_mah = None;
+    let mut items_mah = 0.0;
+    let mut attribution = Vec::<EnergyConsumerAttribution>::new();
+    for line in dump.lines() {
+        // <version>,<uid>,<category>,<section>,<values>...
+        let fields = line.split(',').collect::<Vec<_>>();
+        let &[CHECKIN_VERSION, uid, _category, section, ref values @ ..] = fields.as_slice() else {
+            continue;
+        };
+        match (section, values) {
+            // <capacity>,<computed drain>,<min drain>,<max drain>
+            ("pws", [_capacity, computed, ..]) => total_mah = computed.parse::<f64>().ok(),
+            // <label>,<mAh>,...
+            ("pwi", [label, mah, ..]) => {
+                let Ok(mah) = mah.parse::<f64>() else {
+                    continue;
+                };
+                items_mah += mah;
+                if *label == "uid" {
+                    if let Ok(uid) = uid.parse() {
+                        attribution.push(EnergyConsumerAttribution {
+                            uid,
+                            energy_uws: mah_to_uws(mah),
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    EnergyConsumerReading {
+        timestamp,
+        energy_uws: mah_to_uws(total_mah.unwrap_or(items_mah)),
+        attribution,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkin() {
+        let dump = "\
+9,0,i,vers,36,214,UQ1A.240205.004,UQ1A.240205.004
+9,0,l,pws,4614,100,90,110
+9,0,l,pwi,scrn,40,0,0,0
+9,0,l,pwi,idle,10,0,0,0
+9,10123,l,pwi,uid,30,0,12.5,0
+9,1000,l,pwi,uid,20,1,0,0
+9,10123,l,pwi,uid,garbage,0,0,0
+8,10456,l,pwi,uid,1000,0,0,0
+";
+        let reading = parse_checkin(dump, Duration::from_secs(42));
+        assert_eq!(reading.timestamp, Duration::from_secs(42));
+        // 100mAh at 3.7V is 1332J
+        assert_eq!(reading.energy_uws, 1_332_000_000);
+        assert_eq!(
+            reading.attribution,
+            [
+                EnergyConsumerAttribution {
+                    uid: 10123,
+                    energy_uws: 399_600_000
+                },
+                EnergyConsumerAttribution {
+                    uid: 1000,
+                    energy_uws: 266_400_000
+                },
+            ]
+        );
+
+        // Without a summary, the total is the sum of all items
+        let without_summary = dump.replace("9,0,l,pws", "9,0,l,xyz");
+        assert_eq!(
+            parse_checkin(&without_summary, Duration::ZERO).energy_uws,
+            mah_to_uws(100.0)
+        );
+    }
+}
@@ -2,6 +2,7 @@ use std::{
     any,
     collections::HashMap,
     fmt,
+    mem::size_of,
     sync::{OnceLock, RwLock},
 };
 
@@ -9,6 +10,7 @@ use binder::{
     binder_impl::{BorrowedParcel, Deserialize, Serialize},
     Parcelable, StatusCode,
 };
+use log::warn;
 
 pub(crate) mod mangled {
     #[allow(non_camel_case_types)]
@@ -16,28 +18,74 @@ pub(crate) mod mangled {
 }
 
 /// https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/Parcelable.java;l=212-231;drc=39d2c720cd407481ebf9d348c7dcd5bc5df6cb8e
-pub trait ParcelableCreator: Send + Sync {
+pub(crate) trait ParcelableCreator: Send + Sync {
     fn create_from_parcel(
         &self,
         parcel: &BorrowedParcel<'_>,
     ) -> Result<Box<dyn ParcelableInstance>, StatusCode>;
 }
 
+/// Registered creators with the [`any::TypeId`] of their concrete type, used to tell a harmless
+/// re-registration apart from a conflicting one
+type Creators = HashMap<&'static str, (any::TypeId, &'static dyn ParcelableCreator)>;
+
 // TODO: Can this be cleaner?
-static CREATORS: OnceLock<RwLock<HashMap<&'static str, &'static dyn ParcelableCreator>>> =
-    OnceLock::new();
+static CREATORS: OnceLock<RwLock<Creators>> = OnceLock::new();
+
+/// Whether `creator` is the one in a registration, which for zero-sized creators (that all share
+/// an address) only takes the type into account
+fn is_registered<C: ParcelableCreator + 'static>(
+    (type_id, registered): (any::TypeId, &'static dyn ParcelableCreator),
+    creator: &'static C,
+) -> bool {
+    type_id == any::TypeId::of::<C>()
+        && (size_of::<C>() == 0
+            || std::ptr::eq(
+                registered as *const dyn ParcelableCreator as *const (),
+                creator as *const C as *const (),
+            ))
+}
 
 // https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/Parcelable.java;l=212;drc=82bdcd7ff7ba4962274f1d88caac0594ae964bef
-pub fn register_creator(name: &'static str, creator: &'static dyn ParcelableCreator) {
-    CREATORS
+/// Registers `creator` for Parcelables named `name`.  A different creator that was already
+/// registered under the same name is kept, with a warning (and an assertion in debug builds), see
+/// [`register_creator_checked()`].
+pub(crate) fn register_creator<C: ParcelableCreator + 'static>(
+    name: &'static str,
+    creator: &'static C,
+) {
+    let result = register_creator_checked(name, creator);
+    if result.is_err() {
+        warn!("A different CREATOR was already registered for `{name}`, keeping it");
+    }
+    debug_assert!(
+        result.is_ok(),
+        "A different CREATOR was already registered for `{name}`"
+    );
+}
+
+/// Same as [`register_creator()`], but fails with [`StatusCode::ALREADY_EXISTS`] (rather than
+/// warning) when a different creator is already registered for `name`, which stays in place.
+/// Registering the same creator again succeeds.
+pub(crate) fn register_creator_checked<C: ParcelableCreator + 'static>(
+    name: &'static str,
+    creator: &'static C,
+) -> Result<(), StatusCode> {
+    let mut creators = CREATORS
         .get_or_init(|| RwLock::new(HashMap::new()))
         .write()
-        .unwrap()
-        .insert(name, creator);
+        .unwrap();
+    match creators.get(name) {
+        Some(&previous) if !is_registered(previous, creator) => Err(StatusCode::ALREADY_EXISTS),
+        _ => {
+            creators.insert(name, (any::TypeId::of::<C>(), creator));
+            Ok(())
+        }
+    }
 }
 
 /// Wrapper-"hack" to make special "parsed [`Parcelable`]s" downcastable via the [`any::Any`] trait
-pub trait ParcelableInstance: Parcelable + any::Any + fmt::Debug {
+pub(crate) trait ParcelableInstance: Parcelable + any::Any + fmt::Debug {
     fn as_any(&self) -> &dyn any::Any;
 }
 
@@ -51,9 +99,25 @@ impl<T: Parcelable + any::Any + fmt::Debug> ParcelableInstance for T {
 #[derive(Debug)]
 pub enum Object {
     Null,
+    // Values of keys that nothing looks up yet are still parsed, and show up in the Debug output
+    #[allow(dead_code)]
+    Integer(i32),
+    #[allow(dead_code)]
+    Long(i64),
+    #[allow(dead_code)]
+    Double(f64),
+    #[allow(dead_code)]
+    Boolean(bool),
+    #[allow(dead_code)]
+    String(String),
     ParcelableArray(Vec<Box<dyn ParcelableInstance>>),
+    #[allow(dead_code)]
     BooleanArray(Vec<bool>),
+    #[allow(dead_code)]
+    IntArray(Vec<i32>),
     LongArray(Vec<i64>),
+    #[allow(dead_code)]
+    DoubleArray(Vec<f64>),
 }
 
 /// https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/BaseBundle.java
@@ -119,25 +183,41 @@ fn is_length_prefixed(r#type: i32) -> bool {
     )
 }
 
+/// Reads an array written as its length followed by its elements of `element_size` bytes each,
+/// i.e. `createLongArray()`.  A negative length (-1) is how i.e. `writeLongArray()` encodes null,
+/// and anything that can't possibly fit in the remainder of the parcel is corrupt: both are
+/// [`Object::Null`].
+fn parcel_read_array<T>(
+    parcel: &BorrowedParcel<'_>,
+    element_size: usize,
+    read_element: impl Fn() -> Result<T, StatusCode>,
+    object: impl FnOnce(Vec<T>) -> Object,
+) -> Result<Object, StatusCode> {
+    let n: i32 = parcel.read()?;
+    let avail = parcel.get_data_size() - parcel.get_data_position();
+    Ok(if n >= 0 && n <= (avail / element_size as i32) {
+        let mut vec = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            vec.push(read_element()?);
+        }
+        object(vec)
+    } else {
+        Object::Null
+    })
+}
+
 fn parcel_read_value(parcel: &BorrowedParcel<'_>, r#type: i32) -> Result<Object, StatusCode> {
     match r#type {
-        VAL_NULL => todo!("VAL_NULL"),
-        VAL_STRING => todo!("VAL_STRING"),
-        VAL_INTEGER => todo!("VAL_INTEGER"),
-        VAL_MAP => todo!("VAL_MAP"),
-        VAL_BUNDLE => todo!("VAL_BUNDLE"),
-        VAL_PARCELABLE => todo!("VAL_PARCELABLE"),
-        VAL_SHORT => todo!("VAL_SHORT"),
-        VAL_LONG => todo!("VAL_LONG"),
-        VAL_FLOAT => todo!("VAL_FLOAT"),
-        VAL_DOUBLE => todo!("VAL_DOUBLE"),
-        VAL_BOOLEAN => todo!("VAL_BOOLEAN"),
-        VAL_CHARSEQUENCE => todo!("VAL_CHARSEQUENCE"),
-        VAL_LIST => todo!("VAL_LIST"),
-        VAL_SPARSEARRAY => todo!("VAL_SPARSEARRAY"),
-        VAL_BYTEARRAY => todo!("VAL_BYTEARRAY"),
-        VAL_STRINGARRAY => todo!("VAL_STRINGARRAY"),
-        VAL_IBINDER => todo!("VAL_IBINDER"),
+        VAL_NULL => Ok(Object::Null),
+        // writeString() is writeString16() in Java, unlike the writeString8() of PowerMonitor names
+        VAL_STRING => Ok(parcel
+            .read::<Option<String>>()?
+            .map_or(Object::Null, Object::String)),
+        VAL_INTEGER => Ok(Object::Integer(parcel.read()?)),
+        VAL_LONG => Ok(Object::Long(parcel.read()?)),
+        VAL_DOUBLE => Ok(Object::Double(parcel.read()?)),
+        // writeBoolean() writes an int
+        VAL_BOOLEAN => Ok(Object::Boolean(parcel.read::<i32>()? != 0)),
         VAL_PARCELABLEARRAY => {
             // readParcelableArrayInternal()
             let n: i32 = parcel.read()?;
@@ -150,91 +230,189 @@ fn parcel_read_value(parcel: &BorrowedParcel<'_>, r#type: i32) -> Result<Object,
                     .expect("No CREATORs were ever registered")
                     .read()
                     .unwrap();
-                let creator = creators
+                let &(_, creator) = creators
                     .get(creator.as_str())
                     .ok_or(StatusCode::NAME_NOT_FOUND)
-                    .inspect_err(|_e| eprintln!("No CREATOR registered for `{creator}`"))?;
+                    .inspect_err(|_e| warn!("No CREATOR registered for `{creator}`"))?;
                 let object = creator.create_from_parcel(parcel)?;
                 vec.push(object);
             }
             Ok(Object::ParcelableArray(vec))
         }
-        VAL_OBJECTARRAY => todo!("VAL_OBJECTARRAY"),
-        VAL_INTARRAY => todo!("VAL_INTARRAY"),
-        VAL_LONGARRAY => {
-            // createLongArray()
-            let n: i32 = parcel.read()?;
-            let mut vec = Vec::with_capacity(n as usize);
-            for _ in 0..n {
-                vec.push(parcel.read()?);
-            }
-            Ok(Object::LongArray(vec))
+        // createIntArray()
+        VAL_INTARRAY => {
+            parcel_read_array(parcel, size_of::<i32>(), || parcel.read(), Object::IntArray)
         }
-        VAL_BYTE => todo!("VAL_BYTE"),
-        VAL_SERIALIZABLE => todo!("VAL_SERIALIZABLE"),
-        VAL_SPARSEBOOLEANARRAY => todo!("VAL_SPARSEBOOLEANARRAY"),
-        VAL_BOOLEANARRAY => {
-            // createBooleanArray()
-            let n: i32 = parcel.read()?;
-            // dbg!(n);
-            let avail = parcel.get_data_size() - parcel.get_data_position();
-            Ok(
-                if n >= 0 && n <= (avail / std::mem::size_of::<i32>() as i32) {
-                    let mut vec = Vec::with_capacity(n as usize);
-                    for _ in 0..n {
-                        let b: i32 = parcel.read()?;
-                        vec.push(b != 0);
-                    }
-                    Object::BooleanArray(vec)
-                } else {
-                    Object::Null
-                },
-            )
+        // createLongArray()
+        VAL_LONGARRAY => parcel_read_array(
+            parcel,
+            size_of::<i64>(),
+            || parcel.read(),
+            Object::LongArray,
+        ),
+        // createBooleanArray(), with an int per element
+        VAL_BOOLEANARRAY => parcel_read_array(
+            parcel,
+            size_of::<i32>(),
+            || Ok(parcel.read::<i32>()? != 0),
+            Object::BooleanArray,
+        ),
+        // createDoubleArray()
+        VAL_DOUBLEARRAY => parcel_read_array(
+            parcel,
+            size_of::<f64>(),
+            || parcel.read(),
+            Object::DoubleArray,
+        ),
+        // Length-prefixed types among these are skipped by `parcel_read_value_type()` instead
+        VAL_MAP
+        | VAL_BUNDLE
+        | VAL_PARCELABLE
+        | VAL_SHORT
+        | VAL_FLOAT
+        | VAL_CHARSEQUENCE
+        | VAL_LIST
+        | VAL_SPARSEARRAY
+        | VAL_BYTEARRAY
+        | VAL_STRINGARRAY
+        | VAL_IBINDER
+        | VAL_OBJECTARRAY
+        | VAL_BYTE
+        | VAL_SERIALIZABLE
+        | VAL_SPARSEBOOLEANARRAY
+        | VAL_CHARSEQUENCEARRAY
+        | VAL_PERSISTABLEBUNDLE
+        | VAL_SIZE
+        | VAL_SIZEF
+        | VAL_CHAR
+        | VAL_SHORTARRAY
+        | VAL_CHARARRAY
+        | VAL_FLOATARRAY => {
+            warn!("Unsupported Parcel value type {type}");
+            Err(StatusCode::BAD_VALUE)
+        }
+        t => {
+            warn!("Unknown Parcel value type {t}");
+            Err(StatusCode::BAD_VALUE)
+        }
+    }
+}
+
+/// Failure to read a single value of a [`Bundle`], with the context that the [`StatusCode`] it is
+/// reported to binder as lacks
+#[derive(Debug, PartialEq, Eq)]
+enum ValueError {
+    Status(StatusCode),
+    /// A length-prefixed value consumed a different number of bytes than it declared, i.e.
+    /// because a newer framework appended fields.  Reported as [`StatusCode::BAD_VALUE`].
+    LengthMismatch {
+        r#type: i32,
+        declared: i32,
+        consumed: i32,
+    },
+}
+
+impl From<StatusCode> for ValueError {
+    fn from(status: StatusCode) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<ValueError> for StatusCode {
+    fn from(e: ValueError) -> Self {
+        match e {
+            ValueError::Status(status) => status,
+            ValueError::LengthMismatch { .. } => Self::BAD_VALUE,
+        }
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "{status:?}"),
+            Self::LengthMismatch {
+                r#type,
+                declared,
+                consumed,
+            } => write!(
+                f,
+                "Value of type {type} declared {declared} bytes but consumed {consumed}"
+            ),
         }
-        VAL_CHARSEQUENCEARRAY => todo!("VAL_CHARSEQUENCEARRAY"),
-        VAL_PERSISTABLEBUNDLE => todo!("VAL_PERSISTABLEBUNDLE"),
-        VAL_SIZE => todo!("VAL_SIZE"),
-        VAL_SIZEF => todo!("VAL_SIZEF"),
-        VAL_DOUBLEARRAY => todo!("VAL_DOUBLEARRAY"),
-        VAL_CHAR => todo!("VAL_CHAR"),
-        VAL_SHORTARRAY => todo!("VAL_SHORTARRAY"),
-        VAL_CHARARRAY => todo!("VAL_CHARARRAY"),
-        VAL_FLOATARRAY => todo!("VAL_FLOATARRAY"),
-        t => todo!("Unknown Parcel value type {t}"),
     }
 }
 
 /// https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/core/java/android/os/Parcel.java;l=4528;drc=190beaa49a35da1d9dcf66be9cfccfd23b0eb467
-fn parcel_read_value_type(parcel: &BorrowedParcel<'_>) -> Result<Object, StatusCode> {
+fn parcel_read_value_type(parcel: &BorrowedParcel<'_>) -> Result<Object, ValueError> {
     let t: i32 = parcel.read()?;
     // dbg!(&t);
     if is_length_prefixed(t) {
         let length: i32 = parcel.read()?;
         // dbg!(length);
         let start = parcel.get_data_position();
+        // The only length-prefixed type that is parsed, the prefix exists so that others can be
+        // skipped
+        if t != VAL_PARCELABLEARRAY {
+            let end = start
+                .checked_add(length)
+                .filter(|&end| length >= 0 && end <= parcel.get_data_size())
+                .ok_or(StatusCode::BAD_VALUE)?;
+            warn!("Skipping {length} bytes of unsupported Parcel value type {t}");
+            // SAFETY: Within the data of the parcel, which is all that set_data_position() requires
+            unsafe { parcel.set_data_position(end) }?;
+            return Ok(Object::Null);
+        }
         let r = parcel_read_value(parcel, t)?;
-        assert_eq!(parcel.get_data_position(), start + length);
+        let end = parcel.get_data_position();
+        if end != start + length {
+            // A mismatched device mustn't take the callback thread down with it, but a mismatch
+            // can be made loud while working on the parser with
+            // `RUSTFLAGS="--cfg powerstats_strict_bundles"`
+            #[cfg(powerstats_strict_bundles)]
+            debug_assert_eq!(end, start + length, "Length prefix mismatch for type {t}");
+            return Err(ValueError::LengthMismatch {
+                r#type: t,
+                declared: length,
+                consumed: end - start,
+            });
+        }
         Ok(r)
     } else {
-        parcel_read_value(parcel, t)
+        Ok(parcel_read_value(parcel, t)?)
     }
 }
 
+/// Reads a string written by `writeString8()`, i.e. UTF-8 bytes with a trailing `\0`.  This is what
+/// `PowerMonitor` uses for its name, as opposed to UTF-16 strings written by `writeString()` (such
+/// as [`Bundle`] keys) which are read through binder's own [`String`] deserializer instead.
+///
+/// Invalid UTF-8 (some OEMs put non-ASCII characters in rail names, not necessarily encoded
+/// correctly) is replaced with `U+FFFD` rather than failing the whole parcel.  A truncated parcel
+/// fails with [`StatusCode::NOT_ENOUGH_DATA`] and a missing terminator with
+/// [`StatusCode::BAD_VALUE`].
+///
 /// https://cs.android.com/android/platform/superproject/main/+/main:frameworks/native/libs/binder/Parcel.cpp;l=2261;drc=82bdcd7ff7ba4962274f1d88caac0594ae964bef
 pub fn parcel_read_string8(parcel: &BorrowedParcel<'_>) -> Result<String, StatusCode> {
     // TODO: This is wrong, we must also parse a trailing \0 _before_ padding the parcel to 4 bytes again
     let len: u32 = parcel.read()?;
-    let len_with_nul = len + 1;
+    let len_with_nul = len.checked_add(1).ok_or(StatusCode::BAD_VALUE)?;
+    let num_words = len_with_nul.div_ceil(4) as usize;
+    // Don't read garbage (or allocate) for a length that can't fit in the rest of a truncated parcel
+    let avail = parcel.get_data_size() - parcel.get_data_position();
+    if num_words > avail as usize / size_of::<u32>() {
+        return Err(StatusCode::NOT_ENOUGH_DATA);
+    }
     // TODO: Reading contiguous arrays of predetermined (rounded up for strings) size is hard with current API
     // Read the as UTF-8 characters with a terminating null, while keeping the parcel 4-byte aligned
-    let words = (0..len_with_nul.div_ceil(4) as usize)
+    let words = (0..num_words)
         .map(|_| parcel.read())
         .collect::<Result<Vec<u32>, StatusCode>>()?;
-    let chars = bytemuck::cast_slice(&words);
-    assert_eq!(chars[len as usize], b'\0');
-    // TODO: Need to move
-    let str = std::str::from_utf8(&chars[..len as usize]).unwrap();
-    Ok(str.to_owned())
+    let chars: &[u8] = bytemuck::cast_slice(&words);
+    if chars[len as usize] != b'\0' {
+        return Err(StatusCode::BAD_VALUE);
+    }
+    Ok(String::from_utf8_lossy(&chars[..len as usize]).into_owned())
 }
 
 impl Deserialize for Bundle {
@@ -243,8 +421,12 @@ impl Deserialize for Bundle {
 
         // Parse nullability because of writeTypedObject
         // https://cs.android.com/android/platform/superproject/main/+/main:out/soong/.intermediates/frameworks/base/framework-minus-apex-intdefs/android_common/e18b8e8d84cb9f664aa09a397b08c165/xref50/srcjars.xref/com/android/internal/os/IResultReceiver.java;l=118;drc=190beaa49a35da1d9dcf66be9cfccfd23b0eb467
+        // The service sends null alongside an error code such as `RESULT_UNSUPPORTED_POWER_MONITOR`,
+        // which is read as an empty Bundle so that the receiver gets to look at that code
         let is_set: i32 = parcel.read()?;
-        assert!(is_set == 1);
+        if is_set == 0 {
+            return Ok(Self(HashMap::new()));
+        }
 
         let length: i32 = parcel.read()?;
         // dbg!(length);
@@ -270,9 +452,345 @@ impl Deserialize for Bundle {
             // dbg!(&str);
 
             // TODO: optimization for sorted parcels!
-            map.insert(str, parcel_read_value_type(parcel)?);
+            let value = parcel_read_value_type(parcel).map_err(|e| {
+                warn!("Failed to read Bundle value {str:?}: {e}");
+                StatusCode::from(e)
+            })?;
+            map.insert(str, value);
         }
 
         Ok(Self(map))
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use binder::binder_impl::Parcel;
+
+    use super::*;
+
+    /// Builds a [`Parcel`] with `f` and rewinds it so that it can be read back
+    pub(crate) fn parcel_with(
+        f: impl FnOnce(&mut BorrowedParcel<'_>) -> Result<(), StatusCode>,
+    ) -> Parcel {
+        let mut parcel = Parcel::new();
+        f(&mut parcel.borrowed()).unwrap();
+        unsafe { parcel.set_data_position(0) }.unwrap();
+        parcel
+    }
+
+    /// Counterpart of [`parcel_read_string8()`], laid out like `writeString8()`
+    pub(crate) fn write_string8(
+        parcel: &mut BorrowedParcel<'_>,
+        bytes: &[u8],
+    ) -> Result<(), StatusCode> {
+        parcel.write(&(bytes.len() as u32))?;
+        let mut with_nul = bytes.to_vec();
+        with_nul.push(b'\0');
+        for word in with_nul.chunks(4) {
+            let mut padded = [0; 4];
+            padded[..word.len()].copy_from_slice(word);
+            parcel.write(&u32::from_ne_bytes(padded))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn string8_non_ascii() {
+        let name = "[VSYS_PWR_MÖDEM]:Modem µW";
+        let parcel = parcel_with(|p| {
+            write_string8(p, name.as_bytes())?;
+            p.write(&42i32)
+        });
+        assert_eq!(parcel_read_string8(parcel.borrowed_ref()).unwrap(), name);
+        // Must have consumed exactly the padded string
+        assert_eq!(parcel.read::<i32>().unwrap(), 42);
+    }
+
+    #[test]
+    fn string8_invalid_utf8() {
+        // Latin-1 encoded `Ö`
+        let parcel = parcel_with(|p| write_string8(p, b"M\xd6DEM"));
+        assert_eq!(
+            parcel_read_string8(parcel.borrowed_ref()).unwrap(),
+            "M\u{fffd}DEM"
+        );
+    }
+
+    #[test]
+    fn null_bundle() {
+        // writeTypedObject(null), followed by whatever the caller writes next
+        let parcel = parcel_with(|p| {
+            p.write(&0i32)?;
+            p.write(&42i32)
+        });
+        let Bundle(map) = parcel.read().unwrap();
+        assert!(map.is_empty());
+        assert_eq!(parcel.read::<i32>().unwrap(), 42);
+    }
+
+    #[test]
+    fn length_prefix_mismatch() {
+        // An empty parcelable array only takes up its 4-byte count, not the declared 8
+        let parcel = parcel_with(|p| {
+            p.write(&VAL_PARCELABLEARRAY)?;
+            p.write(&8i32)?;
+            p.write(&0i32)
+        });
+        assert_eq!(
+            parcel_read_value_type(parcel.borrowed_ref()).unwrap_err(),
+            ValueError::LengthMismatch {
+                r#type: VAL_PARCELABLEARRAY,
+                declared: 8,
+                consumed: 4
+            }
+        );
+    }
+
+    #[test]
+    fn long_array() {
+        let parcel = parcel_with(|p| {
+            p.write(&VAL_LONGARRAY)?;
+            p.write(&2i32)?;
+            p.write(&7i64)?;
+            p.write(&-3i64)
+        });
+        let object = parcel_read_value_type(parcel.borrowed_ref()).unwrap();
+        assert!(matches!(object, Object::LongArray(v) if v == [7, -3]));
+    }
+
+    #[test]
+    fn null_long_array() {
+        let parcel = parcel_with(|p| {
+            p.write(&VAL_LONGARRAY)?;
+            p.write(&-1i32)
+        });
+        let object = parcel_read_value_type(parcel.borrowed_ref()).unwrap();
+        assert!(matches!(object, Object::Null));
+    }
+
+    #[test]
+    fn oversized_long_array() {
+        // Claims a million elements but only carries two
+        let parcel = parcel_with(|p| {
+            p.write(&VAL_LONGARRAY)?;
+            p.write(&1_000_000i32)?;
+            p.write(&7i64)?;
+            p.write(&-3i64)
+        });
+        let object = parcel_read_value_type(parcel.borrowed_ref()).unwrap();
+        assert!(matches!(object, Object::Null));
+    }
+
+    /// `VAL_PARCELABLEARRAY` with a single `PowerMonitor` of `type`, followed by `extra` fields
+    fn parcel_with_monitor(r#type: i32, extra: &'static [i32]) -> Parcel {
+        crate::android_os_powerstatsservice::register_power_monitor_creator();
+        parcel_with(move |p| {
+            p.write(&VAL_PARCELABLEARRAY)?;
+            let length_pos = p.get_data_position();
+            p.write(&0i32)?;
+            p.write(&1i32)?;
+            p.write("android.os.PowerMonitor")?;
+            p.write(&3i32)?;
+            p.write(&r#type)?;
+            write_string8(p, b"[VSYS_PWR_MODEM]:Modem")?;
+            for field in extra {
+                p.write(field)?;
+            }
+            // Patch the length prefix now that the size of the value is known
+            let end = p.get_data_position();
+            unsafe { p.set_data_position(length_pos) }?;
+            p.write(&(end - length_pos - size_of::<i32>() as i32))?;
+            unsafe { p.set_data_position(end) }
+        })
+    }
+
+    /// Reads the single monitor of [`parcel_with_monitor()`]
+    fn read_monitor(parcel: &Parcel) -> crate::android_os_powerstatsservice::PowerMonitor {
+        let Object::ParcelableArray(monitors) =
+            parcel_read_value_type(parcel.borrowed_ref()).unwrap()
+        else {
+            panic!("Must have ParcelableArray")
+        };
+        monitors[0]
+            .as_any()
+            .downcast_ref::<crate::android_os_powerstatsservice::PowerMonitor>()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn power_monitor_trailing_fields() {
+        let monitor = read_monitor(&parcel_with_monitor(1, &[]));
+        assert_eq!(
+            (monitor.index, monitor.name.as_str()),
+            (3, "[VSYS_PWR_MODEM]:Modem")
+        );
+
+        // A field appended by a newer framework must not be decoded into a bogus monitor
+        let parcel = parcel_with_monitor(1, &[42]);
+        assert!(matches!(
+            parcel_read_value_type(parcel.borrowed_ref()).unwrap_err(),
+            ValueError::LengthMismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn power_monitor_unknown_type() {
+        use crate::android_os_powerstatsservice::PowerMonitorType;
+
+        let monitor = read_monitor(&parcel_with_monitor(2, &[]));
+        assert_eq!(monitor.r#type, PowerMonitorType::Unknown(2));
+        assert_eq!(monitor.name, "[VSYS_PWR_MODEM]:Modem");
+    }
+
+    #[test]
+    fn string8_truncated() {
+        // Claims 32 bytes but the parcel ends after the first word
+        let parcel = parcel_with(|p| {
+            p.write(&32u32)?;
+            p.write(&u32::from_ne_bytes(*b"VSYS"))
+        });
+        assert_eq!(
+            parcel_read_string8(parcel.borrowed_ref()).unwrap_err(),
+            StatusCode::NOT_ENOUGH_DATA
+        );
+
+        // Fits, but isn't terminated
+        let parcel = parcel_with(|p| {
+            p.write(&4u32)?;
+            p.write(&u32::from_ne_bytes(*b"VSYS"))?;
+            p.write(&u32::from_ne_bytes(*b"_PWR"))
+        });
+        assert_eq!(
+            parcel_read_string8(parcel.borrowed_ref()).unwrap_err(),
+            StatusCode::BAD_VALUE
+        );
+    }
+
+    #[test]
+    fn conflicting_creators() {
+        struct First;
+        struct Second;
+        impl ParcelableCreator for First {
+            fn create_from_parcel(
+                &self,
+                _parcel: &BorrowedParcel<'_>,
+            ) -> Result<Box<dyn ParcelableInstance>, StatusCode> {
+                Err(StatusCode::UNKNOWN_ERROR)
+            }
+        }
+        impl ParcelableCreator for Second {
+            fn create_from_parcel(
+                &self,
+                _parcel: &BorrowedParcel<'_>,
+            ) -> Result<Box<dyn ParcelableInstance>, StatusCode> {
+                Err(StatusCode::BAD_TYPE)
+            }
+        }
+
+        const NAME: &str = "test.ConflictingCreators";
+        assert_eq!(register_creator_checked(NAME, &First), Ok(()));
+        assert_eq!(register_creator_checked(NAME, &First), Ok(()));
+        assert_eq!(
+            register_creator_checked(NAME, &Second),
+            Err(StatusCode::ALREADY_EXISTS)
+        );
+        // Unchecked registrations don't replace it either, which debug builds assert on only after
+        // leaving the registry as it was
+        let unchecked = std::panic::catch_unwind(|| register_creator(NAME, &Second));
+        assert_eq!(unchecked.is_err(), cfg!(debug_assertions));
+        // The first registration stays in place
+        let creators = CREATORS.get().unwrap().read().unwrap();
+        assert_eq!(creators[NAME].0, any::TypeId::of::<First>());
+    }
+
+    #[test]
+    fn primitive_values() {
+        let parcel = parcel_with(|p| {
+            // writeTypedObject() and the Bundle header
+            p.write(&1i32)?;
+            let length_pos = p.get_data_position();
+            p.write(&0i32)?;
+            p.write(&0x4C444E42i32)?;
+            let start = p.get_data_position();
+            p.write(&8i32)?;
+            p.write("integer")?;
+            p.write(&VAL_INTEGER)?;
+            p.write(&-7i32)?;
+            p.write("long")?;
+            p.write(&VAL_LONG)?;
+            p.write(&(1i64 << 40))?;
+            p.write("string")?;
+            p.write(&VAL_STRING)?;
+            p.write("µW")?;
+            p.write("boolean")?;
+            p.write(&VAL_BOOLEAN)?;
+            p.write(&1i32)?;
+            p.write("double")?;
+            p.write(&VAL_DOUBLE)?;
+            p.write(&0.25f64)?;
+            p.write("ints")?;
+            p.write(&VAL_INTARRAY)?;
+            p.write(&[3i32, -4][..])?;
+            p.write("doubles")?;
+            p.write(&VAL_DOUBLEARRAY)?;
+            p.write(&[1.5f64][..])?;
+            p.write("null")?;
+            p.write(&VAL_NULL)?;
+            // Patch the length (which excludes the magic) now that the size is known
+            let end = p.get_data_position();
+            unsafe { p.set_data_position(length_pos) }?;
+            p.write(&(end - start))?;
+            unsafe { p.set_data_position(end) }
+        });
+        let Bundle(map) = parcel.read().unwrap();
+        assert_eq!(map.len(), 8);
+        assert!(matches!(map["integer"], Object::Integer(-7)));
+        assert!(matches!(map["long"], Object::Long(l) if l == 1 << 40));
+        assert!(matches!(&map["string"], Object::String(s) if s == "µW"));
+        assert!(matches!(map["boolean"], Object::Boolean(true)));
+        assert!(matches!(map["double"], Object::Double(d) if d == 0.25));
+        assert!(matches!(&map["ints"], Object::IntArray(v) if v == &[3, -4]));
+        assert!(matches!(&map["doubles"], Object::DoubleArray(v) if v == &[1.5]));
+        assert!(matches!(map["null"], Object::Null));
+    }
+
+    #[test]
+    fn unsupported_values() {
+        // Skipped through its length prefix
+        let parcel = parcel_with(|p| {
+            p.write(&VAL_SERIALIZABLE)?;
+            p.write(&8i32)?;
+            p.write(&1i64)?;
+            p.write(&42i32)
+        });
+        assert!(matches!(
+            parcel_read_value_type(parcel.borrowed_ref()),
+            Ok(Object::Null)
+        ));
+        assert_eq!(parcel.read::<i32>().unwrap(), 42);
+
+        // A length running past the end of the parcel
+        let parcel = parcel_with(|p| {
+            p.write(&VAL_LIST)?;
+            p.write(&64i32)
+        });
+        assert_eq!(
+            parcel_read_value_type(parcel.borrowed_ref()).unwrap_err(),
+            ValueError::Status(StatusCode::BAD_VALUE)
+        );
+
+        // Can't be skipped
+        for t in [VAL_FLOAT, 1234] {
+            let parcel = parcel_with(|p| {
+                p.write(&t)?;
+                p.write(&0.5f32)
+            });
+            assert_eq!(
+                parcel_read_value_type(parcel.borrowed_ref()).unwrap_err(),
+                ValueError::Status(StatusCode::BAD_VALUE)
+            );
+        }
+    }
+}
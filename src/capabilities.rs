@@ -0,0 +1,89 @@
+use anyhow::Result;
+
+use crate::{BackendSelection, PowerStats};
+
+/// Outcome of probing every optional capability of a device through
+/// [`PowerStats::capability_report()`], meant to be pasted (in its [`Debug`] form) into bug
+/// reports.  Every probe that failed carries the error that it failed with, rather than failing
+/// the whole report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapabilityReport {
+    pub backend: BackendSelection,
+    /// See [`PowerStats::hal_version()`]
+    pub hal_version: Result<i32, String>,
+    /// Number of discovered meters
+    pub meters: Result<usize, String>,
+    /// Number of discovered consumers
+    pub consumers: Result<usize, String>,
+    /// Whether any consumer reading attributes energy to UIDs.  An idle device may legitimately
+    /// have nothing to attribute yet.
+    pub attribution: Result<bool, String>,
+    /// Number of power entities that report state residency
+    pub state_residency: Result<usize, String>,
+    /// See [`PowerStats::duration_is_reliable()`]
+    pub duration_is_reliable: Result<bool, String>,
+}
+
+impl PowerStats {
+    /// Runs every capability probe in turn, which takes at least [`crate::DURATION_PROBE_INTERVAL`]
+    pub fn capability_report(&self) -> CapabilityReport {
+        fn probe<T>(result: Result<T>) -> Result<T, String> {
+            result.map_err(|e| format!("{e:#}"))
+        }
+
+        CapabilityReport {
+            backend: self.backend.selection(),
+            hal_version: probe(self.hal_version()),
+            meters: probe(self.energy_meters().map(|m| m.len())),
+            consumers: probe(self.energy_consumers().map(|c| c.len())),
+            attribution: probe(self.read_all_consumers_with_attribution().map(|readings| {
+                readings
+                    .iter()
+                    .any(|(_, reading)| !reading.attribution.is_empty())
+            })),
+            state_residency: probe(self.read_all_state_residency().map(|r| r.len())),
+            duration_is_reliable: probe(self.duration_is_reliable()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{android_hardware_power_stats::EnergyConsumerType as HalType, mock::*};
+
+    #[test]
+    fn report() {
+        // Meters and attributed consumers, but no power entities
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                ])
+            }),
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids.iter().map(|&id| measurement(id, 1000, 0, 42)).collect())
+            }),
+            energy_consumer_info: Box::new(|| Ok(vec![consumer(10, 0, HalType::OTHER, "GPU")])),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000, 300, &[(10123, 300)]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let report = stats.capability_report();
+        assert_eq!(report.backend, BackendSelection::VendorHardwareService);
+        assert_eq!(report.hal_version, Ok(1));
+        assert_eq!(report.meters, Ok(2));
+        assert_eq!(report.consumers, Ok(1));
+        assert_eq!(report.attribution, Ok(true));
+        assert!(report.state_residency.is_err());
+        // Durations never advance
+        assert_eq!(report.duration_is_reliable, Ok(false));
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{EnergyMeterReading, PowerStats};
+
+type Slot = Arc<Mutex<Option<(Instant, Vec<EnergyMeterReading>)>>>;
+
+/// Shares [`PowerStats::read_energy_meters()`] results between readers of the same set of ids,
+/// i.e. multiple subsystems in one process that all sample the GPU rail at a high frequency.
+/// A read that arrives within `window` of the previous binder call for the same ids (or while
+/// that call is still in flight) is served from its result instead of issuing another one.
+///
+/// This trades freshness for binder traffic: readings may be up to `window` old.  Pick a window
+/// well below the sampling period of every reader, or they'll see the same reading twice and
+/// compute zero power.
+#[derive(Debug)]
+pub struct CoalescingReader<S: Deref<Target = PowerStats>> {
+    stats: S,
+    window: Duration,
+    slots: Mutex<HashMap<Vec<i32>, Slot>>,
+}
+
+impl<S: Deref<Target = PowerStats>> CoalescingReader<S> {
+    pub fn new(stats: S, window: Duration) -> Self {
+        Self {
+            stats,
+            window,
+            slots: Default::default(),
+        }
+    }
+
+    /// Same as [`PowerStats::read_energy_meters()`], but possibly served from the result of a
+    /// concurrent or recent read of exactly the same `meter_ids`.  Errors aren't shared, the next
+    /// reader retries.
+    pub fn read_energy_meters(&self, meter_ids: &[i32]) -> Result<Vec<EnergyMeterReading>> {
+        let slot = Arc::clone(
+            self.slots
+                .lock()
+                .unwrap()
+                .entry(meter_ids.to_vec())
+                .or_default(),
+        );
+        // Held for the whole binder call, so that concurrent readers wait for its result
+        let mut slot = slot.lock().unwrap();
+        if let Some((read_at, readings)) = &*slot {
+            if read_at.elapsed() <= self.window {
+                return Ok(readings.clone());
+            }
+        }
+        let readings = self.stats.read_energy_meters(meter_ids)?;
+        *slot = Some((Instant::now(), readings.clone()));
+        Ok(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, thread};
+
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn coalesces_concurrent_reads() {
+        let (stats, calls) = MockPowerStats {
+            read_energy_meter: Box::new(|ids| {
+                // Keep the call in flight long enough for the other reader to arrive
+                thread::sleep(Duration::from_millis(50));
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 10, 42))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let reader = CoalescingReader::new(&stats, Duration::from_secs(60));
+        let barrier = Barrier::new(2);
+        let results = thread::scope(|s| {
+            let threads = [(), ()].map(|()| {
+                s.spawn(|| {
+                    barrier.wait();
+                    reader.read_energy_meters(&[3]).unwrap()
+                })
+            });
+            threads.map(|t| t.join().unwrap())
+        });
+        assert_eq!(results[0], results[1]);
+        assert_eq!(*calls.lock().unwrap(), [("readEnergyMeter", vec![3])]);
+
+        // A different set of ids isn't served from the same result
+        reader.read_energy_meters(&[3, 4]).unwrap();
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+}
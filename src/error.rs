@@ -0,0 +1,68 @@
+use std::{error, fmt};
+
+/// Failures specific to this crate.  Methods on [`crate::PowerStats`] return [`anyhow::Result`],
+/// use [`anyhow::Error::downcast_ref()`] to tell these apart from the underlying binder errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PowerStatsError {
+    /// The service did not deliver a result before the requested deadline
+    Timeout,
+    /// The service reported multiple energy meters with this id, which is a firmware bug
+    DuplicateMeterId(i32),
+    /// The service refused the call to `method`
+    PermissionDenied { method: &'static str },
+    /// `method` is not available on [`crate::BackendSelection::SystemJavaService`] or
+    /// [`crate::BackendSelection::BatteryStats`], or on a recording or replay of the vendor HAL
+    Unsupported { method: &'static str },
+    /// A stored profile was written by a semver-incompatible version of this crate
+    IncompatibleProfileVersion { found: String },
+    /// The Java service reported this nonzero result code instead of a result
+    ServiceReturnedError(i32),
+    /// The service returned no reading for this id, or rejected it, while reading it as part of a
+    /// `_partial` batch
+    MissingReading(i32),
+    /// The Java service reported a power monitor whose name doesn't follow the `[name]:subsystem`
+    /// (measurements) or `type/ordinal` (consumers) convention
+    MalformedMonitorName { raw: String },
+    /// The Java service reported success, but its result `Bundle` lacked `key` or held a value of
+    /// the wrong type under it
+    MalformedBundle { key: &'static str },
+}
+
+impl fmt::Display for PowerStatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("Timed out waiting for the power stats service"),
+            Self::DuplicateMeterId(id) => {
+                write!(f, "Energy meter id {id} is reported more than once")
+            }
+            Self::PermissionDenied { method } => write!(
+                f,
+                "Permission denied calling `{method}`, the `powerstats` service requires \
+                 `android.permission.POWER_MONITORS` while the vendor HAL is typically blocked by \
+                 SELinux for apps"
+            ),
+            Self::Unsupported { method } => {
+                write!(f, "`{method}` is not available on the selected backend")
+            }
+            Self::IncompatibleProfileVersion { found } => write!(
+                f,
+                "Profile was written by version {found}, which is incompatible with version {}",
+                env!("CARGO_PKG_VERSION")
+            ),
+            Self::ServiceReturnedError(code) => {
+                write!(f, "The power stats service returned result code {code}")
+            }
+            Self::MissingReading(id) => write!(f, "No reading was returned for id {id}"),
+            Self::MalformedMonitorName { raw } => write!(f, "Malformed power monitor name {raw:?}"),
+            Self::MalformedBundle { key } => {
+                write!(
+                    f,
+                    "The power stats service returned no valid `{key}` in its result"
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for PowerStatsError {}
@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, net::ToSocketAddrs};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::Serialize;
+use tiny_http::{Header, Response, Server};
+
+use crate::PowerStats;
+
+impl PowerStats {
+    /// Serves discovery and readings as JSON over HTTP until the server fails, for quick debugging
+    /// with i.e. `adb forward tcp:8080 tcp:8080`:
+    ///
+    /// - `/meters`: [`Self::energy_meters()`]
+    /// - `/consumers`: [`Self::energy_consumers()`]
+    /// - `/readings?ids=0,1,2`: [`Self::read_energy_meters_map()`], for all meters without `ids`
+    ///
+    /// Requests are handled one at a time on the calling thread.
+    pub fn serve(self, addr: impl ToSocketAddrs) -> Result<()> {
+        let server = Server::http(addr).map_err(|e| anyhow!(e))?;
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+        for request in server.incoming_requests() {
+            let (status, body) = self.respond(request.url());
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(content_type.clone());
+            if let Err(e) = request.respond(response) {
+                warn!("Failed to respond to HTTP request: {e:?}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Status code and JSON body for a request to `url`
+    fn respond(&self, url: &str) -> (u16, String) {
+        fn json(result: Result<impl Serialize>) -> (u16, String) {
+            match result.and_then(|value| Ok(serde_json::to_string(&value)?)) {
+                Ok(body) => (200, body),
+                Err(e) => error(500, &format!("{e:#}")),
+            }
+        }
+        fn error(status: u16, message: &str) -> (u16, String) {
+            (status, serde_json::json!({ "error": message }).to_string())
+        }
+
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        match path {
+            "/meters" => json(self.energy_meters()),
+            "/consumers" => json(self.energy_consumers()),
+            "/readings" => {
+                let ids = match query.split('&').find_map(|q| q.strip_prefix("ids=")) {
+                    Some(ids) => match ids.split(',').map(str::parse).collect() {
+                        Ok(ids) => ids,
+                        Err(e) => return error(400, &format!("Malformed `ids` {ids:?}: {e}")),
+                    },
+                    None => match self.energy_meters() {
+                        Ok(meters) => meters.iter().map(|m| m.id).collect::<Vec<i32>>(),
+                        Err(e) => return error(500, &format!("{e:#}")),
+                    },
+                };
+                json(
+                    self.read_energy_meters_map(&ids)
+                        .map(|readings| readings.into_iter().collect::<BTreeMap<_, _>>()),
+                )
+            }
+            _ => error(404, &format!("Unknown path {path:?}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{android_hardware_power_stats::EnergyConsumerType as HalType, mock::*};
+
+    #[test]
+    fn endpoints() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                ])
+            }),
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 10, 100 * id as i64))
+                    .collect())
+            }),
+            energy_consumer_info: Box::new(|| Ok(vec![consumer(10, 0, HalType::OTHER, "GPU")])),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let get = |url| {
+            let (status, body) = stats.respond(url);
+            (status, serde_json::from_str::<Value>(&body).unwrap())
+        };
+
+        let (status, meters) = get("/meters");
+        assert_eq!(status, 200);
+        assert_eq!(meters[1]["name"], "S2M_VDD_CPUCL2");
+
+        let (status, consumers) = get("/consumers");
+        assert_eq!(status, 200);
+        assert_eq!(consumers[0]["id"], 10);
+
+        let (status, readings) = get("/readings?ids=1");
+        assert_eq!(status, 200);
+        assert_eq!(readings.as_object().unwrap().len(), 1);
+        assert_eq!(readings["1"]["energy_uws"], 100);
+
+        let (_, readings) = get("/readings");
+        assert_eq!(readings.as_object().unwrap().len(), 2);
+
+        let (status, error) = get("/readings?ids=1,x");
+        assert_eq!(status, 400);
+        assert!(error["error"].is_string());
+        assert_eq!(get("/foo").0, 404);
+    }
+}
@@ -0,0 +1,145 @@
+use std::vec;
+
+use anyhow::Result;
+
+use crate::{EnergyMeter, EnergyMeterReading, PowerStats, PowerStatsError, MAX_IDS_PER_READ};
+
+/// Reads meters lazily in batches of at most `MAX_IDS_PER_READ`, returned by
+/// [`PowerStats::iter_meter_readings()`]
+#[derive(Debug)]
+pub struct MeterReadings<'a> {
+    stats: &'a PowerStats,
+    meters: vec::IntoIter<EnergyMeter>,
+    batch: vec::IntoIter<(EnergyMeter, EnergyMeterReading)>,
+}
+
+impl Iterator for MeterReadings<'_> {
+    type Item = Result<(EnergyMeter, EnergyMeterReading)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(next) = self.batch.next() {
+            return Some(Ok(next));
+        }
+
+        let meters = self
+            .meters
+            .by_ref()
+            .take(MAX_IDS_PER_READ)
+            .collect::<Vec<_>>();
+        if meters.is_empty() {
+            return None;
+        }
+        let ids = meters.iter().map(|m| m.id).collect::<Vec<_>>();
+        match self.stats.read_energy_meters(&ids) {
+            Ok(readings) if readings.len() < meters.len() => {
+                self.meters = Vec::new().into_iter();
+                Some(Err(PowerStatsError::MissingReading(
+                    meters[readings.len()].id,
+                )
+                .into()))
+            }
+            Ok(readings) => {
+                self.batch = meters
+                    .into_iter()
+                    .zip(readings)
+                    .collect::<Vec<_>>()
+                    .into_iter();
+                self.batch.next().map(Ok)
+            }
+            Err(e) => {
+                // Don't keep trying the remainder
+                self.meters = Vec::new().into_iter();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl PowerStats {
+    /// Discovers all meters and reads them one batch at a time while iterating, rather than
+    /// holding on to the readings of every meter at once.  Only readings are bounded to a batch:
+    /// discovery still returns all meters in a single call, and the iterator holds on to every
+    /// [`EnergyMeter`] that it hasn't yielded yet.  A batch for which the service returned fewer readings than meters
+    /// yields [`PowerStatsError::MissingReading`] and ends the iteration, like any failed read.
+    pub fn iter_meter_readings(&self) -> Result<MeterReadings<'_>> {
+        Ok(MeterReadings {
+            stats: self,
+            meters: self.energy_meters()?.into_iter(),
+            batch: Vec::new().into_iter(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn bounded_batches() {
+        let (stats, calls) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok((0..150)
+                    .map(|id| channel(id, &format!("RAIL{id}"), "SoC"))
+                    .collect())
+            }),
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 1000, id as i64))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut iter = stats.iter_meter_readings().unwrap();
+        let (meter, reading) = iter.next().unwrap().unwrap();
+        assert_eq!((meter.id, reading.energy_uws), (0, 0));
+        // Only the first batch was read so far
+        assert_eq!(calls.lock().unwrap().len(), 2);
+
+        let rest = iter.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(rest.len(), 149);
+        for (meter, reading) in &rest {
+            assert_eq!(reading.energy_uws, meter.id as i64);
+        }
+
+        let calls = calls.lock().unwrap();
+        let batches = calls[1..]
+            .iter()
+            .map(|(_, ids)| ids.len())
+            .collect::<Vec<_>>();
+        assert_eq!(batches, [64, 64, 22]);
+    }
+
+    #[test]
+    fn short_batch() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok((0..3)
+                    .map(|id| channel(id, &format!("RAIL{id}"), "SoC"))
+                    .collect())
+            }),
+            // Leaves out an invalid meter
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .filter(|&&id| id != 1)
+                    .map(|&id| measurement(id, 1000, 1000, id as i64))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut iter = stats.iter_meter_readings().unwrap();
+        let err = iter.next().unwrap().unwrap_err();
+        // Readings aren't tagged with their id, so which one is reported missing is only positional
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PowerStatsError::MissingReading(_))
+        ));
+        assert!(iter.next().is_none());
+    }
+}
@@ -1,19 +1,209 @@
 #![warn(unused_qualifications)]
 
-use std::{fmt, str::FromStr, time::Duration};
+use std::{
+    collections::{hash_map, BTreeMap, HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::{mpsc::Receiver, Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
 use android_hardware_power_stats::{
     BpPowerStats, Channel, EnergyConsumerResult, EnergyMeasurement, IPowerStats,
 };
-use android_os_powerstatsservice::{IPowerStatsService, PowerMonitorType};
+use android_os_powerstatsservice::{
+    IPowerStatsService, PowerMonitor, PowerMonitorReadings, PowerMonitorType, ReceiverResult,
+};
 use anyhow::Result;
-use binder::Strong;
+use binder::{
+    binder_impl::{IBinderInternal, TransactionCode, FIRST_CALL_TRANSACTION},
+    ExceptionCode, SpIBinder, StatusCode, Strong, ThreadState,
+};
 use log::warn;
 
+pub use batterystats::{BATTERY_STATS_CONSUMER_ID, NOMINAL_BATTERY_VOLTAGE};
+#[cfg(feature = "testing")]
+pub use capabilities::CapabilityReport;
+pub use coalesce::CoalescingReader;
+pub use error::PowerStatsError;
+pub use iter::MeterReadings;
+#[cfg(feature = "android-pm")]
+pub use packages::{PackageResolver, PackagesList};
+pub use prefetch::PrefetchReader;
+#[cfg(feature = "serde")]
+pub use profile::DeviceProfile;
+pub use residency::{PowerEntity, PowerEntityState, StateResidency, StateResidencyResult};
+pub use sampler::{Clock, MeterSmoothing, PowerSample, PowerSampler, ReadingClock};
+pub use timeline::UidPowerTimeline;
+pub use topology::DeviceTopology;
+
 mod android_hardware_power_stats;
 mod android_os_powerstatsservice;
+mod batterystats;
 mod bundle;
+#[cfg(feature = "testing")]
+mod capabilities;
+mod coalesce;
+mod error;
+#[cfg(feature = "http")]
+mod http;
+mod iter;
+#[cfg(test)]
+mod mock;
+#[cfg(feature = "android-pm")]
+mod packages;
+mod prefetch;
+#[cfg(feature = "serde")]
+mod profile;
+mod record;
+mod residency;
 mod result_receiver;
+mod sampler;
+#[cfg(feature = "tokio")]
+mod stream;
+mod timeline;
+mod topology;
+
+/// Internals for `benches/`, not part of the public API
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use std::collections::HashMap;
+
+    use binder::{binder_impl::BorrowedParcel, BinderFeatures, StatusCode};
+
+    use crate::{
+        android_hardware_power_stats::{
+            BnPowerStats, Channel, EnergyConsumer, EnergyConsumerResult, EnergyMeasurement,
+            IPowerStats, PowerEntity, StateResidencyResult,
+        },
+        android_os_powerstatsservice::powerstatsservice::{
+            BnPowerStatsService, IPowerStatsService, KEY_ENERGY, KEY_TIMESTAMPS,
+        },
+        bundle::{Bundle, Object},
+        mangled::_7_android_2_os_14_ResultReceiver as ResultReceiver,
+        Backend, EnergyMeterReading, PowerStats,
+    };
+
+    /// Deserializes a `Bundle` as the Java service sends it to a `ResultReceiver`, and returns its
+    /// number of entries
+    pub fn deserialize_bundle(parcel: &BorrowedParcel<'_>) -> Result<usize, StatusCode> {
+        crate::android_os_powerstatsservice::register_power_monitor_creator();
+        parcel.read::<Bundle>().map(|b| b.0.len())
+    }
+
+    /// In-process stand-in for the Java service, which answers every read of power monitors
+    /// immediately and holds on to nothing
+    struct ImmediateService;
+
+    impl binder::Interface for ImmediateService {}
+
+    #[allow(non_snake_case)]
+    impl IPowerStatsService for ImmediateService {
+        fn getSupportedPowerMonitors(&self, _receiver: &ResultReceiver) -> binder::Result<()> {
+            Err(StatusCode::UNKNOWN_TRANSACTION.into())
+        }
+
+        fn getPowerMonitorReadings(
+            &self,
+            ids: &[i32],
+            receiver: &ResultReceiver,
+        ) -> binder::Result<()> {
+            let bundle = Bundle(HashMap::from([
+                (
+                    KEY_TIMESTAMPS.to_string(),
+                    Object::LongArray(vec![1000; ids.len()]),
+                ),
+                (
+                    KEY_ENERGY.to_string(),
+                    Object::LongArray(vec![0; ids.len()]),
+                ),
+            ]));
+            receiver.binder().r#send(0, &bundle)
+        }
+    }
+
+    /// [`PowerStats`] on [`BackendSelection::SystemJavaService`] through an in-process service,
+    /// so that reads only cost the setup on this side rather than a round trip to `system_server`
+    ///
+    /// [`BackendSelection::SystemJavaService`]: crate::BackendSelection::SystemJavaService
+    pub fn java_power_stats() -> PowerStats {
+        PowerStats::from_backend(Backend::SystemJavaService(BnPowerStatsService::new_binder(
+            ImmediateService,
+            BinderFeatures::default(),
+        )))
+    }
+
+    /// In-process stand-in for the vendor HAL with `rails` meters, which answers every read
+    /// immediately
+    struct ManyRails {
+        rails: i32,
+    }
+
+    impl binder::Interface for ManyRails {}
+
+    #[allow(non_snake_case)]
+    impl IPowerStats for ManyRails {
+        fn getPowerEntityInfo(&self) -> binder::Result<Vec<PowerEntity>> {
+            Err(StatusCode::UNKNOWN_TRANSACTION.into())
+        }
+
+        fn getStateResidency(&self, _ids: &[i32]) -> binder::Result<Vec<StateResidencyResult>> {
+            Err(StatusCode::UNKNOWN_TRANSACTION.into())
+        }
+
+        fn getEnergyConsumerInfo(&self) -> binder::Result<Vec<EnergyConsumer>> {
+            Err(StatusCode::UNKNOWN_TRANSACTION.into())
+        }
+
+        fn getEnergyConsumed(&self, _ids: &[i32]) -> binder::Result<Vec<EnergyConsumerResult>> {
+            Err(StatusCode::UNKNOWN_TRANSACTION.into())
+        }
+
+        fn getEnergyMeterInfo(&self) -> binder::Result<Vec<Channel>> {
+            Ok((0..self.rails)
+                .map(|id| Channel {
+                    id,
+                    name: format!("S2M_VDD_RAIL{id}"),
+                    subsystem: "SoC".to_string(),
+                })
+                .collect())
+        }
+
+        fn readEnergyMeter(&self, ids: &[i32]) -> binder::Result<Vec<EnergyMeasurement>> {
+            Ok(ids
+                .iter()
+                .map(|&id| EnergyMeasurement {
+                    id,
+                    timestampMs: 1000,
+                    durationMs: 1000,
+                    energyUWs: 0,
+                })
+                .collect())
+        }
+    }
+
+    /// [`PowerStats`] on [`BackendSelection::VendorHardwareService`] through an in-process HAL
+    /// with `rails` meters
+    ///
+    /// [`BackendSelection::VendorHardwareService`]: crate::BackendSelection::VendorHardwareService
+    pub fn vendor_power_stats(rails: i32) -> PowerStats {
+        PowerStats::from_backend(Backend::VendorHardwareService(BnPowerStats::new_binder(
+            ManyRails { rails },
+            BinderFeatures::default(),
+        )))
+    }
+
+    /// [`PowerStats::read_energy_meters()`] with a new `ResultReceiver`, as every read did before
+    /// they were pooled
+    pub fn read_energy_meters_fresh_receiver(
+        stats: &PowerStats,
+        meter_ids: &[i32],
+    ) -> anyhow::Result<Vec<EnergyMeterReading>> {
+        *stats.readings_receiver.lock().unwrap() = None;
+        stats.read_energy_meters(meter_ids)
+    }
+}
 
 pub(crate) mod mangled {
     pub(crate) use super::android_hardware_power_stats::mangled::*;
@@ -21,19 +211,39 @@ pub(crate) mod mangled {
     pub(crate) use super::result_receiver::mangled::*;
 }
 
+#[derive(Clone)]
 enum Backend {
     VendorHardwareService(Strong<dyn IPowerStats>),
     SystemJavaService(Strong<dyn IPowerStatsService>),
+    /// Not an interface with generated bindings, only its `dump()` output is used
+    BatteryStats(SpIBinder),
 }
 
-impl fmt::Debug for Backend {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Backend {
+    fn selection(&self) -> BackendSelection {
+        match self {
+            Backend::VendorHardwareService(_) => BackendSelection::VendorHardwareService,
+            Backend::SystemJavaService(_) => BackendSelection::SystemJavaService,
+            Backend::BatteryStats(_) => BackendSelection::BatteryStats,
+        }
+    }
+
+    /// Interface descriptor, or the service name for [`Backend::BatteryStats`] which has no
+    /// (associated) interface class
+    fn descriptor(&self) -> String {
         let mut b = match self {
             Backend::VendorHardwareService(s) => s.as_binder(),
             Backend::SystemJavaService(s) => s.as_binder(),
+            Backend::BatteryStats(_) => return "batterystats".to_string(),
         };
+        b.get_class().unwrap().get_descriptor()
+    }
+}
+
+impl fmt::Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Backend")
-            .field("descriptor", &b.get_class().unwrap().get_descriptor())
+            .field("descriptor", &self.descriptor())
             .finish_non_exhaustive()
         // f.write_str(match self {
         //     Backend::VendorHardwareService(_) => "android.hardware.power.stats.IPowerStats/default",
@@ -46,25 +256,451 @@ impl fmt::Debug for Backend {
 pub enum BackendSelection {
     VendorHardwareService,
     SystemJavaService,
+    /// Coarse per-UID energy from the `batterystats` service, for devices without either of the
+    /// above.  Fidelity is far below the other backends:
+    ///
+    /// - There are no meters, and only a single [`BATTERY_STATS_CONSUMER_ID`] consumer covering the
+    ///   entire device;
+    /// - Energy is _modeled_ by the framework from its power profile and usage time, not measured;
+    /// - It is reported as charge in `mAh`, which is converted at [`NOMINAL_BATTERY_VOLTAGE`];
+    /// - Values have a resolution of several `mAh`, accumulate since the statistics were last
+    ///   reset (typically when the device was unplugged) rather than since boot, and may thus go
+    ///   backwards;
+    /// - Reading requires `android.permission.DUMP`, which is only granted to the shell.
+    BatteryStats,
+}
+
+/// How [`PowerStats::connect()`] established its connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub backend: BackendSelection,
+    /// Interface descriptor of the service, i.e. `android.hardware.power.stats.IPowerStats`
+    pub descriptor: String,
+    /// [`PowerStats::hal_version()`] on [`BackendSelection::VendorHardwareService`], [`None`] on
+    /// the other backends or if it couldn't be queried
+    pub hal_version: Option<i32>,
+    /// Whether the preferred [`BackendSelection::SystemJavaService`] was unavailable
+    pub fallback_used: bool,
+}
+
+/// `FIRST_CALL_TRANSACTION + 16777214`, reserved for `getInterfaceVersion()` on every stable AIDL
+/// interface
+/// <https://cs.android.com/android/platform/superproject/main/+/main:system/tools/aidl/aidl.cpp>
+const GET_INTERFACE_VERSION_TRANSACTION: TransactionCode = FIRST_CALL_TRANSACTION + 0x00ff_fffe;
+
+/// Upper bound on the number of ids passed to a single read transaction.  Binder transactions share
+/// a 1MiB buffer per process and consumer readings carry a variable amount of attribution data, so
+/// batched reads are split up to stay well clear of that limit.
+const MAX_IDS_PER_READ: usize = 64;
+
+/// Time between both reads of [`PowerStats::duration_is_reliable()`]
+pub const DURATION_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maps a [`binder::Status`] from calling `method` to [`PowerStatsError::PermissionDenied`] if
+/// the service refused the caller, which some builds only do per call rather than when acquiring the
+/// service.  Other failures are passed through unchanged.
+pub(crate) fn permission_denied(
+    method: &'static str,
+) -> impl FnOnce(binder::Status) -> anyhow::Error {
+    move |status| {
+        // Java services throw a SecurityException, native ones return the status code directly
+        if status.exception_code() == ExceptionCode::SECURITY
+            || status.transaction_error() == StatusCode::PERMISSION_DENIED
+        {
+            PowerStatsError::PermissionDenied { method }.into()
+        } else {
+            status.into()
+        }
+    }
+}
+
+/// Reads `ids` in one batch through `read`, which returns readings tagged with their id, and
+/// falls back to reading them one by one if the batch fails for a reason that may be caused by
+/// a single id
+fn read_partial<T>(
+    ids: &[i32],
+    read: impl Fn(&[i32]) -> Result<Vec<(i32, T)>>,
+) -> Result<Vec<Result<T, PowerStatsError>>> {
+    // Failures that would repeat for every single id
+    let is_fatal = |e: &anyhow::Error| {
+        matches!(
+            e.downcast_ref(),
+            Some(
+                PowerStatsError::PermissionDenied { .. }
+                    | PowerStatsError::Unsupported { .. }
+                    | PowerStatsError::Timeout
+                    | PowerStatsError::MalformedBundle { .. }
+            )
+        )
+    };
+    let take = |readings: &mut Vec<(i32, T)>, id: i32| {
+        let i = readings
+            .iter()
+            .position(|(r, _)| *r == id)
+            .ok_or(PowerStatsError::MissingReading(id))?;
+        Ok(readings.swap_remove(i).1)
+    };
+
+    match read(ids) {
+        Ok(mut readings) => Ok(ids.iter().map(|&id| take(&mut readings, id)).collect()),
+        Err(e) if is_fatal(&e) => Err(e),
+        Err(e) => {
+            warn!("Reading {ids:?} failed with `{e:?}`, reading every id separately");
+            ids.iter()
+                .map(|&id| match read(&[id]) {
+                    Ok(mut readings) => Ok(take(&mut readings, id)),
+                    Err(e) if is_fatal(&e) => Err(e),
+                    Err(_) => Ok(Err(PowerStatsError::MissingReading(id))),
+                })
+                .collect()
+        }
+    }
+}
+
+/// Parses a [`PowerMonitorType::Measurement`] named `[name]:subsystem`
+fn meter_from_monitor(pm: PowerMonitor) -> Result<EnergyMeter, PowerStatsError> {
+    let parsed = pm.name.split_once(':').and_then(|(name, subsystem)| {
+        let name = name.strip_prefix('[')?.strip_suffix(']')?;
+        Some((name.to_string(), subsystem.to_string()))
+    });
+    match parsed {
+        Some((name, subsystem)) => Ok(EnergyMeter::new(pm.index, name, subsystem)),
+        None => Err(PowerStatsError::MalformedMonitorName { raw: pm.name }),
+    }
+}
+
+/// Parses a [`PowerMonitorType::Consumer`] named `type/ordinal`, or just `type` for ordinal `0`
+fn consumer_from_monitor(pm: PowerMonitor) -> Result<EnergyConsumer, PowerStatsError> {
+    let (type_name, ordinal) = match pm.name.split_once('/') {
+        Some((type_name, ordinal)) => match ordinal.parse() {
+            Ok(ordinal) => (type_name.to_string(), ordinal),
+            Err(_) => return Err(PowerStatsError::MalformedMonitorName { raw: pm.name }),
+        },
+        None => (pm.name, 0),
+    };
+    // i.e. GPU is Other
+    let r#type = type_name.parse().unwrap_or(EnergyConsumerType::Other);
+    Ok(EnergyConsumer {
+        id: pm.index,
+        name: type_name,
+        ordinal,
+        r#type,
+        raw_type: r#type.raw(),
+    })
+}
+
+/// Logs and drops monitors that [`meter_from_monitor()`] or [`consumer_from_monitor()`] can't
+/// parse, so that one oddly named monitor doesn't hide all others
+fn skip_malformed<T>(parsed: Result<T, PowerStatsError>) -> Option<T> {
+    parsed
+        .inspect_err(|e| warn!("Skipping power monitor: {e}"))
+        .ok()
+}
+
+/// Tags `readings` returned in the order of `ids` with their id, for [`read_partial()`].  There's
+/// no telling which id is missing if the service returned fewer readings.
+fn by_position<T>(ids: &[i32], readings: Vec<T>) -> Result<Vec<(i32, T)>> {
+    if readings.len() != ids.len() {
+        return Err(binder::Status::from(StatusCode::BAD_VALUE).into());
+    }
+    Ok(ids.iter().copied().zip(readings).collect())
+}
+
+/// `ids` without duplicates, in the order in which every id first appears
+fn unique_ids(ids: &[i32]) -> Vec<i32> {
+    let mut seen = HashSet::with_capacity(ids.len());
+    ids.iter().copied().filter(|&id| seen.insert(id)).collect()
 }
 
+/// `readings` of [`unique_ids()`] in the order of `ids`, repeating the reading of every duplicate
+fn in_order_of<T: Clone>(ids: &[i32], readings: &HashMap<i32, T>) -> Result<Vec<T>> {
+    ids.iter()
+        .map(|id| {
+            readings
+                .get(id)
+                .cloned()
+                .ok_or_else(|| PowerStatsError::MissingReading(*id).into())
+        })
+        .collect()
+}
+
+/// Converts the `field` of the reading for `id` that the service reported in milliseconds, which
+/// must not be negative
+#[track_caller]
+fn duration_from_millis(ms: i64, field: &str, id: i32) -> Duration {
+    match u64::try_from(ms) {
+        Ok(ms) => Duration::from_millis(ms),
+        Err(_) => panic!("`{field}` of the reading for id {id} is negative: {ms}ms"),
+    }
+}
+
+/// Readings of a `getPowerMonitorReadings` round trip for `ids`, in the same order
+fn power_monitor_readings(readings: PowerMonitorReadings, ids: &[i32]) -> Vec<EnergyMeterReading> {
+    readings
+        .timestamps_ms
+        .into_iter()
+        .zip(readings.energy_uws)
+        .zip(ids)
+        .map(|((t, e), &id)| EnergyMeterReading {
+            timestamp: duration_from_millis(t, "timestampMs", id),
+            // TODO: Help, for meters the system service "conveniently" ignores the durationMs field?
+            // Makes it so that we can not even calculate proper deltas since the last call, since noise
+            // will be inserted based on the previous and current value.
+            // https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/services/core/java/com/android/server/powerstats/PowerStatsService.java;l=767-779;drc=515faa7cf81b33607b7926600265be5c58ef300d
+            duration: None,
+            energy_uws: e,
+        })
+        .collect()
+}
+
+type ReadingsReceiver = (
+    result_receiver::ResultReceiver,
+    Receiver<ReceiverResult<PowerMonitorReadings>>,
+);
+
+/// Handle to either power stats service.  Clones talk to the same service and share what was
+/// discovered through it, see [`Self::refresh()`].
 #[derive(Debug)]
 pub struct PowerStats {
     backend: Backend,
+    /// Reused across [`Self::read_power_monitors()`] calls.  The Java service has no synchronous
+    /// way to read monitors, and setting up a new [`result_receiver::ResultReceiver`] binder for
+    /// every read is relatively costly for high-frequency sampling.
+    readings_receiver: Mutex<Option<ReadingsReceiver>>,
+    /// Result of `getSupportedPowerMonitors()`, which every discovery on
+    /// [`Backend::SystemJavaService`] goes through
+    supported_power_monitors: Arc<RwLock<Option<Vec<PowerMonitor>>>>,
+    /// See [`Self::with_attribution_backfill()`]
+    attribution_backfill: bool,
+    /// See [`Self::with_attribution_limit()`]
+    attribution_limit: Option<usize>,
+    /// See [`Self::connection_generation()`]
+    generation: u64,
+    /// [`Backend::VendorHardwareService`] is a local stand-in from [`Self::record_to()`] or
+    /// [`Self::replay_from()`], which [`Self::reconnect()`] must not replace by the live HAL
+    local_hal: bool,
+}
+
+impl Clone for PowerStats {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            // Not shared, so that clones on different threads don't wait for each other's reads
+            readings_receiver: Mutex::new(None),
+            supported_power_monitors: Arc::clone(&self.supported_power_monitors),
+            attribution_backfill: self.attribution_backfill,
+            attribution_limit: self.attribution_limit,
+            generation: self.generation,
+            local_hal: self.local_hal,
+        }
+    }
 }
 
 impl PowerStats {
+    fn from_backend(backend: Backend) -> Self {
+        Self {
+            backend,
+            readings_receiver: Mutex::new(None),
+            supported_power_monitors: Default::default(),
+            attribution_backfill: false,
+            attribution_limit: None,
+            generation: 0,
+            local_hal: false,
+        }
+    }
+
+    /// Some HALs fill in the attribution of a consumer but leave its total
+    /// [`EnergyConsumerReading::energy_uws`] at zero, expecting clients to sum the attribution
+    /// instead.  With `enabled`, [`Self::read_energy_consumers()`] and
+    /// [`Self::read_energy_consumers_partial()`] replace a zero total by the sum of the attribution
+    /// whenever that sum is positive.  Only affects [`BackendSelection::VendorHardwareService`],
+    /// the other backends don't report a total separately from their attribution.
+    ///
+    /// This is a heuristic: a consumer that legitimately didn't consume anything yet can't carry
+    /// any attribution either, but a HAL that reports a zero total for other reasons (i.e. a
+    /// counter reset) will have it papered over.
+    pub fn with_attribution_backfill(mut self, enabled: bool) -> Self {
+        self.attribution_backfill = enabled;
+        self
+    }
+
+    /// Keeps only the `max` UIDs that consumed the most energy in the
+    /// [`EnergyConsumerReading::attribution`] returned by [`Self::read_energy_consumers()`] and
+    /// [`Self::read_energy_consumers_partial()`], ordered by descending energy.  The energy of all
+    /// other UIDs is summed into a single trailing entry for [`OTHER_ATTRIBUTION_UID`], so that the
+    /// attribution still adds up to the same total.  [`None`] (the default) keeps everything.
+    ///
+    /// This bounds the memory and processing spent on consumers that attribute to thousands of
+    /// UIDs, at the cost of losing track of which UIDs the remainder belongs to.  Note that the
+    /// full list is still received and deserialized by the binder call.
+    pub fn with_attribution_limit(mut self, max: Option<usize>) -> Self {
+        self.attribution_limit = max;
+        self
+    }
+
+    /// Applies [`Self::with_attribution_backfill()`] to a reading from the vendor HAL, then
+    /// [`Self::with_attribution_limit()`]
+    fn consumer_reading(&self, result: EnergyConsumerResult) -> EnergyConsumerReading {
+        let mut reading = EnergyConsumerReading::from(result);
+        if self.attribution_backfill && reading.energy_uws == 0 {
+            let attributed = reading
+                .attribution
+                .iter()
+                .fold(0i64, |sum, a| sum.saturating_add(a.energy_uws));
+            if attributed > 0 {
+                reading.energy_uws = attributed;
+            }
+        }
+        self.limit_attribution(&mut reading);
+        reading
+    }
+
+    /// Applies [`Self::with_attribution_limit()`]
+    fn limit_attribution(&self, reading: &mut EnergyConsumerReading) {
+        let Some(max) = self.attribution_limit else {
+            return;
+        };
+        if reading.attribution.len() <= max {
+            return;
+        }
+        reading
+            .attribution
+            .sort_by_key(|a| std::cmp::Reverse(a.energy_uws));
+        let other = reading
+            .attribution
+            .drain(max..)
+            .fold(0i64, |sum, a| sum.saturating_add(a.energy_uws));
+        reading.attribution.push(EnergyConsumerAttribution {
+            uid: OTHER_ATTRIBUTION_UID,
+            energy_uws: other,
+        });
+    }
+
+    /// Discovers power monitors on the Java service only once for this handle and all of its
+    /// clones, until the next [`Self::refresh()`]
+    fn supported_power_monitors(
+        &self,
+        s: &Strong<dyn IPowerStatsService>,
+    ) -> Result<Vec<PowerMonitor>> {
+        if let Some(monitors) = &*self.supported_power_monitors.read().unwrap() {
+            return Ok(monitors.clone());
+        }
+        let monitors = s.receive_supported_power_monitors(None)?;
+        // A refresh() that completed while this was waiting on the service is at least as recent;
+        // keep it rather than overwriting it with this (possibly older) discovery
+        Ok(self
+            .supported_power_monitors
+            .write()
+            .unwrap()
+            .get_or_insert(monitors)
+            .clone())
+    }
+
+    /// Discovers the meters and consumers of [`BackendSelection::SystemJavaService`] again, for
+    /// this handle and all of its clones.  The vendor HAL is queried on every discovery, for which
+    /// this is a no-op.
+    pub fn refresh(&self) -> Result<()> {
+        self.refresh_by_deadline(None)
+    }
+
+    /// Like [`Self::refresh()`], but gives up with [`PowerStatsError::Timeout`] if the Java service
+    /// doesn't deliver its monitors within `timeout`, keeping the previous discovery.  Discovery
+    /// through [`Self::energy_meters()`] and [`Self::energy_consumers()`] is served from the
+    /// result afterwards, rather than waiting on the service indefinitely.
+    pub fn refresh_timeout(&self, timeout: Duration) -> Result<()> {
+        self.refresh_by_deadline(Some(Instant::now() + timeout))
+    }
+
+    fn refresh_by_deadline(&self, deadline: Option<Instant>) -> Result<()> {
+        if let Backend::SystemJavaService(s) = &self.backend {
+            let monitors = s.receive_supported_power_monitors(deadline)?;
+            *self.supported_power_monitors.write().unwrap() = Some(monitors);
+        }
+        Ok(())
+    }
+
+    /// Version of the vendor HAL as reported by `getInterfaceVersion()`, which every stable AIDL
+    /// interface implements but the generated (unversioned) bindings don't include.  HALs built
+    /// before AIDL interfaces were versioned fail it with [`StatusCode::UNKNOWN_TRANSACTION`], and
+    /// are reported as the earliest version `1` instead.  Fails with
+    /// [`PowerStatsError::Unsupported`] on the other backends.
+    pub fn hal_version(&self) -> Result<i32> {
+        const METHOD: &str = "getInterfaceVersion";
+        let binder = self.vendor_hal(METHOD)?.as_binder();
+        let reply = match binder
+            .prepare_transact()
+            .and_then(|data| binder.submit_transact(GET_INTERFACE_VERSION_TRANSACTION, data, 0))
+        {
+            Err(StatusCode::UNKNOWN_TRANSACTION) => return Ok(1),
+            reply => reply.map_err(|e| permission_denied(METHOD)(e.into()))?,
+        };
+        let status: binder::Status = reply.read()?;
+        if !status.is_ok() {
+            return Err(permission_denied(METHOD)(status));
+        }
+        Ok(reply.read()?)
+    }
+
+    /// The vendor HAL, for functionality that the Java service doesn't expose
+    fn vendor_hal(&self, method: &'static str) -> Result<&Strong<dyn IPowerStats>> {
+        match &self.backend {
+            Backend::VendorHardwareService(s) => Ok(s),
+            Backend::SystemJavaService(_) | Backend::BatteryStats(_) => {
+                Err(PowerStatsError::Unsupported { method }.into())
+            }
+        }
+    }
+
     /// Tries to talk to `android.hardware.power.stats.IPowerStats/default`, otherwise falls back to `powerstats`
     pub fn new() -> Result<Self> {
-        match Self::new_with_backend(BackendSelection::SystemJavaService) {
-            Ok(s) => Ok(s),
+        Self::connect().map(|(s, _)| s)
+    }
+
+    /// Like [`Self::new()`], but also describes how the connection was established, i.e. to log
+    /// at startup
+    pub fn connect() -> Result<(Self, ConnectionInfo)> {
+        Self::connect_with(Self::new_with_backend)
+    }
+
+    fn connect_with(
+        new_with_backend: impl Fn(BackendSelection) -> Result<Self>,
+    ) -> Result<(Self, ConnectionInfo)> {
+        let (s, fallback_used) = match new_with_backend(BackendSelection::SystemJavaService) {
+            Ok(s) => (s, false),
             Err(e) => {
                 warn!("Failed to get `powerstats` service because of `{e:?}`. Falling back to vendor HAL");
-                Self::new_with_backend(BackendSelection::VendorHardwareService).inspect_err(|e| {
+                let s = new_with_backend(BackendSelection::VendorHardwareService).inspect_err(|e| {
                     warn!("Failed to get `android.hardware.power.stats.IPowerStats/default` because of `{e:?}`");
                     // type=1400 audit(0.0:419): avc:  denied  { call } for  scontext=u:r:untrusted_app_32:s0:c13,c257,c512,c768 tcontext=u:r:hal_power_stats_default:s0 tclass=binder permissive=1 app=...
                     warn!("If you see `denied {{ call }} for scontext=..untrusted_app.. tcontext=..hal_power_stats_default..` in `logcat`, issue `setenforce 0` from a root shell to allow access");
-                })
+                })?;
+                (s, true)
+            }
+        };
+        let hal_version = match s.backend {
+            Backend::VendorHardwareService(_) => s
+                .hal_version()
+                .inspect_err(|e| warn!("Failed to query the HAL version because of `{e:?}`"))
+                .ok(),
+            Backend::SystemJavaService(_) | Backend::BatteryStats(_) => None,
+        };
+        let info = ConnectionInfo {
+            backend: s.backend.selection(),
+            descriptor: s.backend.descriptor(),
+            hal_version,
+            fallback_used,
+        };
+        Ok((s, info))
+    }
+
+    /// Like [`Self::new()`], but falls back to [`BackendSelection::BatteryStats`] as a last resort
+    /// if neither power stats service is available
+    pub fn new_with_battery_stats_fallback() -> Result<Self> {
+        match Self::new() {
+            Ok(s) => Ok(s),
+            Err(e) => {
+                warn!("Failed to get any power stats service because of `{e:?}`. Falling back to `batterystats`");
+                Self::new_with_backend(BackendSelection::BatteryStats)
             }
         }
     }
@@ -75,60 +711,167 @@ impl PowerStats {
                 let descriptor = <BpPowerStats as IPowerStats>::get_descriptor();
                 let i =
                     binder::check_interface::<dyn IPowerStats>(&format!("{}/default", descriptor))?;
-                Ok(Self {
-                    backend: Backend::VendorHardwareService(i),
-                })
+                Ok(Self::from_backend(Backend::VendorHardwareService(i)))
             }
             BackendSelection::SystemJavaService => {
                 let i = binder::check_interface::<dyn IPowerStatsService>("powerstats")?;
-                Ok(Self {
-                    backend: Backend::SystemJavaService(i),
-                })
+                Ok(Self::from_backend(Backend::SystemJavaService(i)))
+            }
+            BackendSelection::BatteryStats => Ok(Self::from_backend(Backend::BatteryStats(
+                batterystats::get_service()?,
+            ))),
+        }
+    }
+
+    /// Acquires the service of the current [`BackendSelection`] again, i.e. after it died, and
+    /// forgets everything that was discovered through the previous one.  Clones keep using the
+    /// previous service.  Bumps [`Self::connection_generation()`] on success, and leaves this handle
+    /// untouched on failure.
+    ///
+    /// Fails with [`PowerStatsError::Unsupported`] on handles returned by [`Self::record_to()`]
+    /// and [`Self::replay_from()`], which would otherwise silently stop recording or replaying and
+    /// talk to the live HAL instead.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.reconnect_with(Self::new_with_backend)
+    }
+
+    fn reconnect_with(
+        &mut self,
+        new_with_backend: impl FnOnce(BackendSelection) -> Result<Self>,
+    ) -> Result<()> {
+        if self.local_hal {
+            return Err(PowerStatsError::Unsupported {
+                method: "reconnect",
             }
+            .into());
         }
+        let Self { backend, .. } = new_with_backend(self.backend.selection())?;
+        self.backend = backend;
+        *self.readings_receiver.get_mut().unwrap() = None;
+        self.supported_power_monitors = Default::default();
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Number of times [`Self::reconnect()`] succeeded, starting at `0` for the initial
+    /// connection.  Ids and readings may not carry over to a new connection (i.e. when the service
+    /// restarted and counters were reset), anything that accumulates deltas between reads should
+    /// start over when this changes.  Clones start out with the generation of their original.
+    pub fn connection_generation(&self) -> u64 {
+        self.generation
     }
 
     /// Only returns meters, i.e. individual regulators.  To be used with [`Self::read_energy_meters()`].
+    ///
+    /// Fails with [`PowerStatsError::DuplicateMeterId`] if the service reports the same id more
+    /// than once, as readings can then no longer be attributed to a single meter.  Power monitors
+    /// of the Java service with a [`PowerStatsError::MalformedMonitorName`] are skipped with a
+    /// warning, here and in [`Self::energy_consumers()`].
     pub fn energy_meters(&self) -> Result<Vec<EnergyMeter>> {
-        match &self.backend {
+        let meters = match &self.backend {
             Backend::VendorHardwareService(s) => {
                 // let meters = s.getEnergyConsumerInfo()?;
-                let meters = s.getEnergyMeterInfo()?;
-                Ok(meters
+                let meters = s
+                    .getEnergyMeterInfo()
+                    .map_err(permission_denied("getEnergyMeterInfo"))?;
+                meters
                     .into_iter()
                     .map(
                         |Channel {
                              id,
                              name,
                              subsystem,
-                         }| EnergyMeter {
-                            id,
-                            name,
-                            subsystem,
-                        },
+                         }| EnergyMeter::new(id, name, subsystem),
                     )
-                    .collect())
+                    .collect::<Vec<_>>()
             }
             Backend::SystemJavaService(s) => {
-                let monitors = s.receive_supported_power_monitors()?;
+                let monitors = self.supported_power_monitors(s)?;
 
-                Ok(monitors
+                monitors
                     .into_iter()
                     // Only return measurements directly from power rails, corresponding to the HAL's "energy meter" concept
                     .filter(|pm| pm.r#type == PowerMonitorType::Measurement)
-                    .map(|pm| {
-                        let (name, subsystem) = pm.name.split_once(':').unwrap();
-                        let name = name.strip_prefix('[').unwrap();
-                        let name = name.strip_suffix(']').unwrap();
-                        EnergyMeter {
-                            id: pm.index,
-                            name: name.to_string(),
-                            subsystem: subsystem.to_string(),
-                        }
-                    })
-                    .collect())
+                    .filter_map(|pm| skip_malformed(meter_from_monitor(pm)))
+                    .collect()
             }
+            Backend::BatteryStats(_) => vec![],
+        };
+
+        let mut seen = HashSet::with_capacity(meters.len());
+        if let Some(m) = meters.iter().find(|m| !seen.insert(m.id)) {
+            return Err(PowerStatsError::DuplicateMeterId(m.id).into());
+        }
+        Ok(meters)
+    }
+
+    /// Finds the meter that currently has [`EnergyMeter::stable_id`] `stable_id`, if any
+    pub fn meter_by_stable_id(&self, stable_id: u64) -> Result<Option<EnergyMeter>> {
+        Ok(self
+            .energy_meters()?
+            .into_iter()
+            .find(|m| m.stable_id == stable_id))
+    }
+
+    /// Distinct [`EnergyMeter::subsystem`]s of all meters, sorted alphabetically
+    pub fn subsystems(&self) -> Result<Vec<String>> {
+        let mut subsystems = self
+            .energy_meters()?
+            .into_iter()
+            .map(|m| m.subsystem)
+            .collect::<Vec<_>>();
+        subsystems.sort_unstable();
+        subsystems.dedup();
+        Ok(subsystems)
+    }
+
+    /// Discovers all meters whose [`EnergyMeter::subsystem`] is exactly `subsystem` (as listed by
+    /// [`Self::subsystems()`]) and reads them, i.e. all `GPU` rails at once.  Returns nothing
+    /// without reading when no meter is in `subsystem`.
+    pub fn read_subsystem_meters(
+        &self,
+        subsystem: &str,
+    ) -> Result<Vec<(EnergyMeter, EnergyMeterReading)>> {
+        let meters = self
+            .energy_meters()?
+            .into_iter()
+            .filter(|m| m.subsystem == subsystem)
+            .collect::<Vec<_>>();
+        if meters.is_empty() {
+            // An empty list of ids reads all meters on the HAL
+            return Ok(vec![]);
         }
+        let ids = meters.iter().map(|m| m.id).collect::<Vec<_>>();
+        let mut readings = self.read_energy_meters_map(&ids)?;
+        meters
+            .into_iter()
+            .map(|m| match readings.remove(&m.id) {
+                Some(reading) => Ok((m, reading)),
+                None => Err(PowerStatsError::MissingReading(m.id).into()),
+            })
+            .collect()
+    }
+
+    /// Guesses which meters physically measure (part of) `consumer`, by comparing its name to the
+    /// subsystem of every meter case-insensitively, ignoring parenthesized qualifiers such as in
+    /// `CPU(BIG)`.  I.e. the `GPU` consumer is backed by all meters in the `GPU` subsystem.
+    ///
+    /// This is only a heuristic: subsystem names aren't standardized, consumers may be modeled
+    /// from (a fraction of) rails with different names, and on devices with multiple
+    /// [`EnergyConsumerType::CpuCluster`]s every cluster maps to all CPU meters rather than just
+    /// its own.  Returns an empty [`Vec`] when nothing matches.
+    pub fn consumer_backing_meters(&self, consumer: &EnergyConsumer) -> Result<Vec<EnergyMeter>> {
+        Ok(self
+            .energy_meters()?
+            .into_iter()
+            .filter(|m| {
+                let subsystem = m
+                    .subsystem
+                    .split_once('(')
+                    .map_or(m.subsystem.as_str(), |(s, _)| s);
+                subsystem.trim().eq_ignore_ascii_case(&consumer.name)
+            })
+            .collect())
     }
 
     // /// Only returns consumers (i.e. aggregated data from individual meters/rails/regulators for
@@ -137,7 +880,9 @@ impl PowerStats {
         match &self.backend {
             Backend::VendorHardwareService(s) => {
                 // let meters = s.getEnergyConsumerInfo()?;
-                let meters = s.getEnergyConsumerInfo()?;
+                let meters = s
+                    .getEnergyConsumerInfo()
+                    .map_err(permission_denied("getEnergyConsumerInfo"))?;
                 Ok(meters
                     .into_iter()
                     .map(
@@ -151,83 +896,252 @@ impl PowerStats {
                             name,
                             ordinal,
                             r#type: r#type.into(),
+                            raw_type: r#type.0.into(),
                         },
                     )
                     .collect())
             }
             Backend::SystemJavaService(s) => {
-                let monitors = s.receive_supported_power_monitors()?;
+                let monitors = self.supported_power_monitors(s)?;
 
                 Ok(monitors
                     .into_iter()
                     // Only return consumers
                     .filter(|pm| pm.r#type == PowerMonitorType::Consumer)
-                    .map(
-                        |android_os_powerstatsservice::PowerMonitor {
-                             index,
-                             r#type: _,
-                             name,
-                         }| {
-                            let (type_name, ordinal) = match name.split_once('/') {
-                                Some((type_name, ordinal)) => {
-                                    (type_name.to_string(), ordinal.parse().unwrap())
-                                }
-                                None => (name, 0),
-                            };
-                            // i.e. GPU is Other
-                            let r#type = type_name.parse().unwrap_or(EnergyConsumerType::Other);
-
-                            EnergyConsumer {
-                                id: index,
-                                name: type_name,
-                                ordinal,
-                                r#type,
-                            }
-                        },
-                    )
+                    .filter_map(|pm| skip_malformed(consumer_from_monitor(pm)))
                     .collect())
             }
+            Backend::BatteryStats(_) => Ok(vec![EnergyConsumer {
+                id: BATTERY_STATS_CONSUMER_ID,
+                name: "batterystats".to_string(),
+                ordinal: 0,
+                r#type: EnergyConsumerType::Other,
+                raw_type: EnergyConsumerType::Other.raw(),
+            }]),
         }
     }
 
+    /// Consumers of type `r#type` sorted by [`EnergyConsumer::ordinal`], i.e. CPU clusters in
+    /// cluster order.  Neither backend guarantees to report consumers in that order.
+    pub fn consumers_of_type_ordered(
+        &self,
+        r#type: EnergyConsumerType,
+    ) -> Result<Vec<EnergyConsumer>> {
+        let mut consumers = self.energy_consumers()?;
+        consumers.retain(|c| c.r#type == r#type);
+        consumers.sort_by_key(|c| c.ordinal);
+        Ok(consumers)
+    }
+
     // Same code
     fn read_power_monitors(
+        &self,
         s: &Strong<dyn IPowerStatsService>,
         ids: &[i32],
+        deadline: Option<Instant>,
     ) -> Result<Vec<EnergyMeterReading>> {
-        let (receiver, chan) = android_os_powerstatsservice::ReceivePowerMonitorReadings::new();
-        let receiver = result_receiver::ResultReceiver::new(receiver);
-        // TODO: The caller might wish to reuse the receiver?
+        // Taken out of the pool for the round trip, so that concurrent reads can't receive each
+        // other's results while not holding each other up either: a read that finds the pool
+        // empty uses a receiver of its own
+        let pooled = self.readings_receiver.lock().unwrap().take();
+        let (receiver, chan) = pooled.unwrap_or_else(|| {
+            let (receiver, chan) = android_os_powerstatsservice::ReceivePowerMonitorReadings::new();
+            (result_receiver::ResultReceiver::new(receiver), chan)
+        });
 
-        s.getPowerMonitorReadings(ids, &receiver)?;
-        let readings = chan.recv().unwrap();
-
-        let result = readings
-            .timestamps_ms
-            .into_iter()
-            .zip(readings.energy_uws)
-            .map(|(t, e)| EnergyMeterReading {
-                timestamp: Duration::from_millis(t.try_into().unwrap()),
-                // TODO: Help, for meters the system service "conveniently" ignores the durationMs field?
-                // Makes it so that we can not even calculate proper deltas since the last call, since noise
-                // will be inserted based on the previous and current value.
-                // https://cs.android.com/android/platform/superproject/main/+/main:frameworks/base/services/core/java/com/android/server/powerstats/PowerStatsService.java;l=767-779;drc=515faa7cf81b33607b7926600265be5c58ef300d
-                duration: None,
-                energy_uws: e,
-            })
-            .collect();
-        Ok(result)
+        s.getPowerMonitorReadings(ids, &receiver)
+            .map_err(permission_denied("getPowerMonitorReadings"))?;
+        let readings = match deadline {
+            Some(deadline) => {
+                match chan.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(readings) => readings,
+                    // The result might still arrive later, and must not be mistaken for that of
+                    // the next read, so the receiver isn't returned to the pool
+                    Err(_) => return Err(PowerStatsError::Timeout.into()),
+                }
+            }
+            // `receiver` keeps the sender alive, so this only returns once the service calls back
+            // (if ever), which only a deadline can bound
+            None => chan
+                .recv()
+                .expect("Pooled ResultReceiver dropped its sender"),
+        };
+        let mut pool = self.readings_receiver.lock().unwrap();
+        if pool.is_none() {
+            *pool = Some((receiver, chan));
+        }
+        drop(pool);
+        Ok(power_monitor_readings(readings?, ids))
     }
 
-    /// Returns a list of meter readings in the same order as the ids specified in `meter_ids`
+    /// Returns a list of meter readings in the same order as the ids specified in `meter_ids`.
+    /// Every id is only read once, an id that is passed multiple times gets a copy of the same
+    /// reading at each of its positions.
     pub fn read_energy_meters(&self, meter_ids: &[i32]) -> Result<Vec<EnergyMeterReading>> {
+        let unique = unique_ids(meter_ids);
+        if unique.len() < meter_ids.len() {
+            return in_order_of(meter_ids, &self.read_energy_meters_map(&unique)?);
+        }
         match &self.backend {
             Backend::VendorHardwareService(s) => {
-                let readings = s.readEnergyMeter(meter_ids)?;
+                let readings = s
+                    .readEnergyMeter(meter_ids)
+                    .map_err(permission_denied("readEnergyMeter"))?;
                 let result = readings.into_iter().map(|m| m.into()).collect();
                 Ok(result)
             }
-            Backend::SystemJavaService(s) => Self::read_power_monitors(s, meter_ids),
+            Backend::SystemJavaService(s) => self.read_power_monitors(s, meter_ids, None),
+            Backend::BatteryStats(_) => Err(PowerStatsError::Unsupported {
+                method: "readEnergyMeter",
+            }
+            .into()),
+        }
+    }
+
+    /// Like [`Self::read_energy_meters()`], but one unknown or stale id doesn't fail the whole
+    /// batch: every id gets its own result, [`PowerStatsError::MissingReading`] for the ones the
+    /// service left out or rejected.  Only failures that apply to every id (such as
+    /// [`PowerStatsError::PermissionDenied`]) fail the call as a whole.
+    ///
+    /// HALs either leave invalid ids out or fail the whole call, in which case every id is read
+    /// again separately to find out which ones are valid.
+    pub fn read_energy_meters_partial(
+        &self,
+        meter_ids: &[i32],
+    ) -> Result<Vec<Result<EnergyMeterReading, PowerStatsError>>> {
+        read_partial(meter_ids, |ids| match &self.backend {
+            Backend::VendorHardwareService(s) => {
+                let readings = s
+                    .readEnergyMeter(ids)
+                    .map_err(permission_denied("readEnergyMeter"))?;
+                Ok(readings.into_iter().map(|m| (m.id, m.into())).collect())
+            }
+            _ => by_position(ids, self.read_energy_meters(ids)?),
+        })
+    }
+
+    /// Like [`Self::read_energy_meters()`], but keyed by meter id rather than by position.  The
+    /// vendor HAL tags every reading with its id, the Java service returns them in the requested
+    /// order instead.  Duplicate ids are only read once.
+    pub fn read_energy_meters_map(
+        &self,
+        meter_ids: &[i32],
+    ) -> Result<HashMap<i32, EnergyMeterReading>> {
+        let meter_ids = &unique_ids(meter_ids);
+        match &self.backend {
+            Backend::VendorHardwareService(s) => {
+                let readings = s
+                    .readEnergyMeter(meter_ids)
+                    .map_err(permission_denied("readEnergyMeter"))?;
+                Ok(readings.into_iter().map(|m| (m.id, m.into())).collect())
+            }
+            Backend::SystemJavaService(s) => {
+                let readings = self.read_power_monitors(s, meter_ids, None)?;
+                Ok(meter_ids.iter().copied().zip(readings).collect())
+            }
+            Backend::BatteryStats(_) => Err(PowerStatsError::Unsupported {
+                method: "readEnergyMeter",
+            }
+            .into()),
+        }
+    }
+
+    /// Shorthand for [`PowerSampler::new()`] borrowing this [`PowerStats`]
+    pub fn sampler(&self, meter_ids: &[i32]) -> PowerSampler<&Self> {
+        PowerSampler::new(self, meter_ids)
+    }
+
+    /// Probes whether [`EnergyMeterReading::duration`] actually advances with wall-clock time, by
+    /// reading all meters twice [`DURATION_PROBE_INTERVAL`] apart.  Some HALs report a constant or
+    /// zero duration, in which case power has to be derived from [`EnergyMeterReading::timestamp`]
+    /// deltas like on [`BackendSelection::SystemJavaService`] (which never reports a duration,
+    /// and always returns `false`).  [`PowerSampler`] only ever uses timestamps.
+    pub fn duration_is_reliable(&self) -> Result<bool> {
+        let ids = self
+            .energy_meters()?
+            .iter()
+            .map(|m| m.id)
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(false);
+        }
+
+        let before = Instant::now();
+        let first = self.read_energy_meters(&ids)?;
+        std::thread::sleep(DURATION_PROBE_INTERVAL);
+        let second = self.read_energy_meters(&ids)?;
+        let elapsed = before.elapsed();
+
+        // Readings have millisecond granularity, and the HAL may only refresh them periodically
+        let min = DURATION_PROBE_INTERVAL.mul_f32(0.75);
+        let max = elapsed.mul_f32(1.25) + Duration::from_millis(10);
+        Ok(first
+            .iter()
+            .zip(&second)
+            .all(|(a, b)| match (a.duration, b.duration) {
+                (Some(a), Some(b)) => b.checked_sub(a).is_some_and(|d| d >= min && d <= max),
+                _ => false,
+            }))
+    }
+
+    /// Like [`Self::read_energy_meters()`], but gives up with [`PowerStatsError::Timeout`] once
+    /// `deadline` passes, which composes better with a fixed (frame) budget than a per-call
+    /// timeout.
+    ///
+    /// Nothing is requested from the service at all if `deadline` has already passed.  Calls to
+    /// [`BackendSelection::VendorHardwareService`] are synchronous and cannot be abandoned
+    /// halfway, so the deadline is only checked before issuing them.
+    pub fn read_energy_meters_by_deadline(
+        &self,
+        meter_ids: &[i32],
+        deadline: Instant,
+    ) -> Result<Vec<EnergyMeterReading>> {
+        if Instant::now() >= deadline {
+            return Err(PowerStatsError::Timeout.into());
+        }
+        match &self.backend {
+            Backend::VendorHardwareService(_) | Backend::BatteryStats(_) => {
+                self.read_energy_meters(meter_ids)
+            }
+            // Like read_energy_meters(), duplicates are only read once
+            Backend::SystemJavaService(s) => {
+                let unique = unique_ids(meter_ids);
+                let readings = self.read_power_monitors(s, &unique, Some(deadline))?;
+                if unique.len() == meter_ids.len() {
+                    return Ok(readings);
+                }
+                in_order_of(meter_ids, &unique.into_iter().zip(readings).collect())
+            }
+        }
+    }
+
+    /// Like [`Self::read_energy_meters_by_deadline()`], with a deadline `timeout` from now
+    pub fn read_energy_meters_timeout(
+        &self,
+        meter_ids: &[i32],
+        timeout: Duration,
+    ) -> Result<Vec<EnergyMeterReading>> {
+        self.read_energy_meters_by_deadline(meter_ids, Instant::now() + timeout)
+    }
+
+    /// Like [`Self::read_energy_consumers()`], but gives up with [`PowerStatsError::Timeout`] if
+    /// the Java service doesn't deliver the readings within `timeout`.  As with
+    /// [`Self::read_energy_meters_by_deadline()`], synchronous calls to the other backends can't
+    /// be abandoned and are always waited for.
+    pub fn read_energy_consumers_timeout(
+        &self,
+        consumer_ids: &[i32],
+        timeout: Duration,
+    ) -> Result<Vec<EnergyConsumerReading>> {
+        match &self.backend {
+            Backend::VendorHardwareService(_) | Backend::BatteryStats(_) => {
+                self.read_energy_consumers(consumer_ids)
+            }
+            Backend::SystemJavaService(s) => {
+                let deadline = Instant::now() + timeout;
+                let monitors = self.read_power_monitors(s, consumer_ids, Some(deadline))?;
+                Ok(monitors.into_iter().map(|m| m.into()).collect())
+            }
         }
     }
 
@@ -238,33 +1152,296 @@ impl PowerStats {
     ) -> Result<Vec<EnergyConsumerReading>> {
         match &self.backend {
             Backend::VendorHardwareService(s) => {
-                let readings = s.getEnergyConsumed(consumer_ids)?;
-                let result = readings.into_iter().map(|e| e.into()).collect();
+                let readings = s
+                    .getEnergyConsumed(consumer_ids)
+                    .map_err(permission_denied("getEnergyConsumed"))?;
+                let result = readings
+                    .into_iter()
+                    .map(|e| self.consumer_reading(e))
+                    .collect();
                 Ok(result)
             }
             Backend::SystemJavaService(s) => {
-                let monitors = Self::read_power_monitors(s, consumer_ids)?;
+                let monitors = self.read_power_monitors(s, consumer_ids, None)?;
                 // As soon as the code was generalized, need arised for a separate type. Since the
                 // Java service doesn't provide most of the info anyway, just drop it
                 Ok(monitors.into_iter().map(|m| m.into()).collect())
             }
+            Backend::BatteryStats(s) => {
+                if consumer_ids
+                    .iter()
+                    .any(|&id| id != BATTERY_STATS_CONSUMER_ID)
+                {
+                    return Err(binder::Status::from(StatusCode::BAD_VALUE).into());
+                }
+                let mut reading = batterystats::read(s)?;
+                self.limit_attribution(&mut reading);
+                Ok(vec![reading; consumer_ids.len()])
+            }
+        }
+    }
+
+    /// Like [`Self::read_energy_consumers()`], but with a result per id, see
+    /// [`Self::read_energy_meters_partial()`]
+    pub fn read_energy_consumers_partial(
+        &self,
+        consumer_ids: &[i32],
+    ) -> Result<Vec<Result<EnergyConsumerReading, PowerStatsError>>> {
+        read_partial(consumer_ids, |ids| self.read_energy_consumers_by_id(ids))
+    }
+
+    /// [`Self::read_energy_consumers()`] tagged with their id, which the HAL reports itself rather
+    /// than leaving the caller to match them up by position
+    fn read_energy_consumers_by_id(
+        &self,
+        ids: &[i32],
+    ) -> Result<Vec<(i32, EnergyConsumerReading)>> {
+        match &self.backend {
+            Backend::VendorHardwareService(s) => {
+                let readings = s
+                    .getEnergyConsumed(ids)
+                    .map_err(permission_denied("getEnergyConsumed"))?;
+                Ok(readings
+                    .into_iter()
+                    .map(|r| (r.id, self.consumer_reading(r)))
+                    .collect())
+            }
+            _ => by_position(ids, self.read_energy_consumers(ids)?),
+        }
+    }
+
+    /// Like [`Self::read_energy_consumers()`], but only returns the id, timestamp and accumulated
+    /// energy in `uWs` (uJ) of every consumer, for hot loops that don't care about attribution.
+    /// Note that the HAL still sends (and the generated bindings still deserialize) attribution,
+    /// this merely avoids converting it.
+    pub fn read_energy_consumers_totals_only(
+        &self,
+        consumer_ids: &[i32],
+    ) -> Result<Vec<(i32, Duration, i64)>> {
+        match &self.backend {
+            Backend::VendorHardwareService(s) => {
+                let readings = s
+                    .getEnergyConsumed(consumer_ids)
+                    .map_err(permission_denied("getEnergyConsumed"))?;
+                Ok(readings
+                    .into_iter()
+                    .map(|r| {
+                        let timestamp = duration_from_millis(r.timestampMs, "timestampMs", r.id);
+                        (r.id, timestamp, r.energyUWs)
+                    })
+                    .collect())
+            }
+            Backend::SystemJavaService(s) => {
+                let monitors = self.read_power_monitors(s, consumer_ids, None)?;
+                Ok(consumer_ids
+                    .iter()
+                    .zip(monitors)
+                    .map(|(&id, m)| (id, m.timestamp, m.energy_uws))
+                    .collect())
+            }
+            Backend::BatteryStats(_) => Ok(consumer_ids
+                .iter()
+                .zip(self.read_energy_consumers(consumer_ids)?)
+                .map(|(&id, r)| (id, r.timestamp, r.energy_uws))
+                .collect()),
         }
     }
+
+    /// Reads a mix of meters and consumers, in the order of `rails`.  Costs at most one read of
+    /// either kind.  Fails with [`PowerStatsError::MissingReading`] for the first rail that the
+    /// service returned no reading for, rather than returning fewer readings than `rails`.
+    pub fn read(&self, rails: &[RailRef]) -> Result<Vec<EnergyReading>> {
+        let meter_ids = rails
+            .iter()
+            .filter_map(|r| match r {
+                RailRef::Meter(id) => Some(*id),
+                RailRef::Consumer(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let consumer_ids = rails
+            .iter()
+            .filter_map(|r| match r {
+                RailRef::Meter(_) => None,
+                RailRef::Consumer(id) => Some(*id),
+            })
+            .collect::<Vec<_>>();
+
+        let mut meters = if meter_ids.is_empty() {
+            vec![]
+        } else {
+            self.read_energy_meters(&meter_ids)?
+        }
+        .into_iter();
+        let mut consumers = if consumer_ids.is_empty() {
+            vec![]
+        } else {
+            self.read_energy_consumers(&consumer_ids)?
+        }
+        .into_iter();
+
+        // Readings come back in the order of their ids, a short batch leaves the remaining rails
+        // of its kind without one
+        rails
+            .iter()
+            .map(|&r| match r {
+                RailRef::Meter(id) => meters
+                    .next()
+                    .map(EnergyReading::Meter)
+                    .ok_or(PowerStatsError::MissingReading(id)),
+                RailRef::Consumer(id) => consumers
+                    .next()
+                    .map(EnergyReading::Consumer)
+                    .ok_or(PowerStatsError::MissingReading(id)),
+            })
+            .map(|r| r.map_err(Into::into))
+            .collect()
+    }
+
+    /// Discovers all energy consumers and reads them, including their per-UID attribution.  Costs
+    /// one discovery call and a single read, unless there are so many consumers that the read
+    /// needs to be split into multiple transactions.
+    ///
+    /// Fails with [`PowerStatsError::MissingReading`] if the service left out any of the
+    /// consumers it just reported.
+    pub fn read_all_consumers_with_attribution(
+        &self,
+    ) -> Result<Vec<(EnergyConsumer, EnergyConsumerReading)>> {
+        let consumers = self.energy_consumers()?;
+        let ids = consumers.iter().map(|c| c.id).collect::<Vec<_>>();
+        let mut readings = HashMap::with_capacity(ids.len());
+        for chunk in ids.chunks(MAX_IDS_PER_READ) {
+            readings.extend(self.read_energy_consumers_by_id(chunk)?);
+        }
+        consumers
+            .into_iter()
+            .map(|consumer| {
+                let reading = readings
+                    .remove(&consumer.id)
+                    .ok_or(PowerStatsError::MissingReading(consumer.id))?;
+                Ok((consumer, reading))
+            })
+            .collect()
+    }
+
+    /// Energy in `uWs` (uJ) that every consumer attributes to the UID of this process, i.e. where
+    /// an app is spending its power, sorted by descending energy.  Consumers that attribute
+    /// nothing to this UID are left out, as are the ones beyond
+    /// [`Self::with_attribution_limit()`] that only count towards [`OTHER_ATTRIBUTION_UID`].
+    ///
+    /// Must not be called while handling an incoming binder transaction, where the UID of the
+    /// caller would be used instead.
+    pub fn my_energy_breakdown(&self) -> Result<Vec<(EnergyConsumer, i64)>> {
+        self.energy_breakdown_of(ThreadState::get_calling_uid() as i32)
+    }
+
+    fn energy_breakdown_of(&self, uid: i32) -> Result<Vec<(EnergyConsumer, i64)>> {
+        let mut breakdown = self
+            .read_all_consumers_with_attribution()?
+            .into_iter()
+            .filter_map(|(consumer, reading)| {
+                let energy_uws = reading
+                    .attribution
+                    .iter()
+                    .filter(|a| a.uid == uid)
+                    .fold(0i64, |sum, a| sum.saturating_add(a.energy_uws));
+                (energy_uws != 0).then_some((consumer, energy_uws))
+            })
+            .collect::<Vec<_>>();
+        breakdown.sort_by_key(|&(_, energy_uws)| std::cmp::Reverse(energy_uws));
+        Ok(breakdown)
+    }
+
+    /// Reads all energy consumers and sums their accumulated energy in `uWs` (uJ) per
+    /// [`EnergyConsumerType`], e.g. the total across all [`EnergyConsumerType::CpuCluster`]s.
+    /// Totals saturate at [`i64::MAX`].
+    pub fn energy_by_type(&self) -> Result<HashMap<EnergyConsumerType, i64>> {
+        let mut totals = HashMap::<EnergyConsumerType, i128>::new();
+        for (consumer, reading) in self.read_all_consumers_with_attribution()? {
+            *totals.entry(consumer.r#type).or_default() += i128::from(reading.energy_uws);
+        }
+        Ok(totals
+            .into_iter()
+            .map(|(t, e)| (t, e.clamp(i64::MIN.into(), i64::MAX.into()) as i64))
+            .collect())
+    }
+
+    /// Reads `consumer_ids` and sums their attributed energy in `uWs` (uJ) per UID across all of
+    /// them, i.e. to tell which apps drew the most power overall.  Readings from the vendor HAL
+    /// are accumulated as they are deserialized, without converting them to
+    /// [`EnergyConsumerReading`]s first.  Totals saturate at [`i64::MAX`].
+    pub fn read_consumers_attribution_table(
+        &self,
+        consumer_ids: &[i32],
+    ) -> Result<BTreeMap<i32, i64>> {
+        let mut totals = BTreeMap::<i32, i128>::new();
+        let mut add = |uid, energy_uws| *totals.entry(uid).or_default() += i128::from(energy_uws);
+        for chunk in consumer_ids.chunks(MAX_IDS_PER_READ) {
+            match &self.backend {
+                Backend::VendorHardwareService(s) => {
+                    let readings = s
+                        .getEnergyConsumed(chunk)
+                        .map_err(permission_denied("getEnergyConsumed"))?;
+                    for a in readings.iter().flat_map(|r| &r.attribution) {
+                        add(a.uid, a.energyUWs);
+                    }
+                }
+                _ => {
+                    for reading in self.read_energy_consumers(chunk)? {
+                        for a in reading.attribution {
+                            add(a.uid, a.energy_uws);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(totals
+            .into_iter()
+            .map(|(uid, e)| (uid, e.clamp(i64::MIN.into(), i64::MAX.into()) as i64))
+            .collect())
+    }
 }
 
 #[doc(alias = "android.os.PowerMonitor")]
 #[doc(alias = "android.hardware.power.stats.Channel")]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EnergyMeter {
     pub id: i32,
     pub name: String,
     /// Extracted from the name on [`Backend::SystemJavaService`], where is typically appended with a colon (`:`).
     pub subsystem: String,
+    /// Unlike [`Self::id`] this doesn't change when the HAL renumbers meters across boots, see
+    /// [`Self::stable_id_of()`]
+    pub stable_id: u64,
+}
+
+impl EnergyMeter {
+    pub fn new(id: i32, name: String, subsystem: String) -> Self {
+        Self {
+            id,
+            stable_id: Self::stable_id_of(&name, &subsystem),
+            name,
+            subsystem,
+        }
+    }
+
+    /// 64-bit FNV-1a hash of `name` and `subsystem`, which (unlike [`std::hash::Hash`]) is
+    /// guaranteed to be the same across builds, so that it can be persisted
+    pub fn stable_id_of(name: &str, subsystem: &str) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+        // Separate both with a NUL, so that moving characters from one to the other changes the hash
+        let bytes = name.bytes().chain([0]).chain(subsystem.bytes());
+        bytes.fold(OFFSET_BASIS, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(PRIME)
+        })
+    }
 }
 
 /// <https://cs.android.com/android/platform/superproject/main/+/main:hardware/interfaces/power/stats/aidl/android/hardware/power/stats/EnergyConsumerType.aidl>
 #[doc(alias = "android.hardware.power.stats.EnergyConsumerType")]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EnergyConsumerType {
     Other,
     Bluetooth,
@@ -288,11 +1465,36 @@ impl From<android_hardware_power_stats::EnergyConsumerType> for EnergyConsumerTy
             O::MOBILE_RADIO => Self::MobileRadio,
             O::WIFI => Self::Wifi,
             O::CAMERA => Self::Camera,
-            x => todo!("EnergyConsumerType {x:#?}"),
+            _ => Self::Other,
         }
     }
 }
 
+impl From<EnergyConsumerType> for android_hardware_power_stats::EnergyConsumerType {
+    fn from(value: EnergyConsumerType) -> Self {
+        use EnergyConsumerType as T;
+        match value {
+            T::Other => Self::OTHER,
+            T::Bluetooth => Self::BLUETOOTH,
+            T::CpuCluster => Self::CPU_CLUSTER,
+            T::Display => Self::DISPLAY,
+            T::Gnss => Self::GNSS,
+            T::MobileRadio => Self::MOBILE_RADIO,
+            T::Wifi => Self::WIFI,
+            T::Camera => Self::CAMERA,
+        }
+    }
+}
+
+impl EnergyConsumerType {
+    /// Value of this type in the HAL, for [`EnergyConsumer::raw_type`]
+    fn raw(self) -> i32 {
+        android_hardware_power_stats::EnergyConsumerType::from(self)
+            .0
+            .into()
+    }
+}
+
 impl FromStr for EnergyConsumerType {
     type Err = ();
 
@@ -316,16 +1518,127 @@ impl FromStr for EnergyConsumerType {
 #[doc(alias = "android.os.PowerMonitor")]
 #[doc(alias = "android.hardware.power.stats.EnergyConsumer")]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "EnergyConsumerFields")
+)]
 pub struct EnergyConsumer {
     pub id: i32,
     pub name: String,
     pub ordinal: i32,
+    /// Types that [`EnergyConsumerType`] doesn't model (yet) map to [`EnergyConsumerType::Other`],
+    /// see [`Self::raw_type`]
     pub r#type: EnergyConsumerType,
+    /// Numeric `EnergyConsumerType` as reported by the HAL, for HAL-specific types that
+    /// [`Self::r#type`] can't represent.  The Java service only reports a name, from which this is
+    /// derived through [`Self::r#type`].
+    pub raw_type: i32,
+}
+
+/// [`EnergyConsumer`] as stored by versions that predate [`EnergyConsumer::raw_type`], which is
+/// then derived from [`EnergyConsumer::r#type`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct EnergyConsumerFields {
+    id: i32,
+    name: String,
+    ordinal: i32,
+    r#type: EnergyConsumerType,
+    raw_type: Option<i32>,
+}
+
+#[cfg(feature = "serde")]
+impl From<EnergyConsumerFields> for EnergyConsumer {
+    fn from(c: EnergyConsumerFields) -> Self {
+        Self {
+            id: c.id,
+            name: c.name,
+            ordinal: c.ordinal,
+            r#type: c.r#type,
+            raw_type: c.raw_type.unwrap_or_else(|| c.r#type.raw()),
+        }
+    }
+}
+
+impl EnergyConsumer {
+    /// Identifier of this consumer that is the same on both backends, which format
+    /// [`Self::name`] differently (i.e. `CPUCL0` on the HAL but `CPU` on the Java service), to
+    /// reconcile consumers across backends or use them as config keys:
+    ///
+    /// - [`Self::r#type`] in `snake_case` and [`Self::ordinal`] for typed consumers, disregarding
+    ///   the name: `cpu_cluster_0`;
+    /// - [`Self::name`] for [`EnergyConsumerType::Other`], lowercased with every run of
+    ///   non-alphanumeric characters replaced by a single `_`, and [`Self::ordinal`]: `gpu_0`.
+    pub fn canonical_name(&self) -> String {
+        let prefix = match self.r#type {
+            EnergyConsumerType::Other => {
+                let mut name = String::with_capacity(self.name.len());
+                for word in self
+                    .name
+                    .split(|c: char| !c.is_ascii_alphanumeric())
+                    .filter(|w| !w.is_empty())
+                {
+                    if !name.is_empty() {
+                        name.push('_');
+                    }
+                    name.push_str(&word.to_ascii_lowercase());
+                }
+                return format!("{name}_{}", self.ordinal);
+            }
+            EnergyConsumerType::Bluetooth => "bluetooth",
+            EnergyConsumerType::CpuCluster => "cpu_cluster",
+            EnergyConsumerType::Display => "display",
+            EnergyConsumerType::Gnss => "gnss",
+            EnergyConsumerType::MobileRadio => "mobile_radio",
+            EnergyConsumerType::Wifi => "wifi",
+            EnergyConsumerType::Camera => "camera",
+        };
+        format!("{prefix}_{}", self.ordinal)
+    }
+}
+
+/// [`EnergyMeter::id`]
+pub type MeterId = i32;
+/// [`EnergyConsumer::id`]
+pub type ConsumerId = i32;
+
+/// Either kind of rail, to read both in one go with [`PowerStats::read()`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RailRef {
+    Meter(MeterId),
+    Consumer(ConsumerId),
+}
+
+/// Reading of a [`RailRef`], of the same kind
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EnergyReading {
+    Meter(EnergyMeterReading),
+    Consumer(EnergyConsumerReading),
+}
+
+impl EnergyReading {
+    /// Monotonic timestamp since boot
+    pub fn timestamp(&self) -> Duration {
+        match self {
+            Self::Meter(r) => r.timestamp,
+            Self::Consumer(r) => r.timestamp,
+        }
+    }
+
+    /// Accumulated energy in `uWs` (uJ)
+    pub fn energy_uws(&self) -> i64 {
+        match self {
+            Self::Meter(r) => r.energy_uws,
+            Self::Consumer(r) => r.energy_uws,
+        }
+    }
 }
 
 #[doc(alias = "android.os.PowerMonitorReadings")]
 #[doc(alias = "android.hardware.power.stats.EnergyMeasurement")]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EnergyMeterReading {
     /// Monotonic timestamp since boot
     pub timestamp: Duration,
@@ -338,14 +1651,14 @@ pub struct EnergyMeterReading {
 impl From<EnergyMeasurement> for EnergyMeterReading {
     fn from(value: EnergyMeasurement) -> Self {
         let EnergyMeasurement {
-            id: _,
+            id,
             timestampMs,
             durationMs,
             energyUWs,
         } = value;
         EnergyMeterReading {
-            timestamp: Duration::from_millis(timestampMs.try_into().unwrap()),
-            duration: Some(Duration::from_millis(durationMs.try_into().unwrap())),
+            timestamp: duration_from_millis(timestampMs, "timestampMs", id),
+            duration: Some(duration_from_millis(durationMs, "durationMs", id)),
             energy_uws: energyUWs,
         }
     }
@@ -360,19 +1673,21 @@ pub struct EnergyConsumerReading {
     /// Accumulated energy in `uWs` (uJ)
     pub energy_uws: i64,
 
+    /// As reported by the HAL, which may split a single UID over multiple entries (i.e. per
+    /// thread).  See [`Self::merged_attribution()`].
     pub attribution: Vec<EnergyConsumerAttribution>,
 }
 
 impl From<EnergyConsumerResult> for EnergyConsumerReading {
     fn from(value: EnergyConsumerResult) -> Self {
         let EnergyConsumerResult {
-            id: _,
+            id,
             timestampMs,
             energyUWs,
             attribution,
         } = value;
         Self {
-            timestamp: Duration::from_millis(timestampMs.try_into().unwrap()),
+            timestamp: duration_from_millis(timestampMs, "timestampMs", id),
             energy_uws: energyUWs,
             attribution: attribution.into_iter().map(|e| e.into()).collect(),
         }
@@ -401,6 +1716,124 @@ impl From<EnergyMeterReading> for EnergyConsumerReading {
     }
 }
 
+impl EnergyMeterReading {
+    /// Accumulated energy can't be negative, a negative [`Self::energy_uws`] means the HAL is
+    /// misbehaving (or its counter overflowed).  Only [`EnergyDelta`]s may appear negative.
+    pub fn is_valid(&self) -> bool {
+        self.energy_uws >= 0
+    }
+
+    /// Energy accumulated since an `earlier` reading of the same meter
+    pub fn delta_since(&self, earlier: &Self) -> EnergyDelta {
+        EnergyDelta {
+            start: earlier.timestamp,
+            end: self.timestamp,
+            energy_uws: self.energy_uws.wrapping_sub(earlier.energy_uws),
+        }
+    }
+
+    /// Accumulated energy in `uWs` (uJ) at timestamp `at` between readings `a` and `b` of the same
+    /// meter, i.e. to resample a power trace to a fixed grid.  [`None`] if `at` lies outside of
+    /// `a.timestamp..=b.timestamp`.
+    ///
+    /// Interpolates linearly, which assumes that power was constant between both readings.  That
+    /// only holds for readings that are close together relative to how quickly the load changes.
+    pub fn interpolate(a: &Self, b: &Self, at: Duration) -> Option<f64> {
+        if !(a.timestamp..=b.timestamp).contains(&at) {
+            return None;
+        }
+        let span = (b.timestamp - a.timestamp).as_secs_f64();
+        if span == 0.0 {
+            return Some(a.energy_uws as f64);
+        }
+        let t = (at - a.timestamp).as_secs_f64() / span;
+        let delta = b.energy_uws.wrapping_sub(a.energy_uws) as f64;
+        Some(a.energy_uws as f64 + t * delta)
+    }
+}
+
+impl EnergyConsumerReading {
+    /// Accumulated energy can't be negative, a negative [`Self::energy_uws`] means the HAL is
+    /// misbehaving (or its counter overflowed).  Only [`EnergyDelta`]s may appear negative.
+    pub fn is_valid(&self) -> bool {
+        self.energy_uws >= 0
+    }
+
+    /// Energy accumulated since an `earlier` reading of the same consumer
+    pub fn delta_since(&self, earlier: &Self) -> EnergyDelta {
+        EnergyDelta {
+            start: earlier.timestamp,
+            end: self.timestamp,
+            energy_uws: self.energy_uws.wrapping_sub(earlier.energy_uws),
+        }
+    }
+
+    /// [`Self::attribution`] with the energy of duplicate UIDs summed (saturating) into a single
+    /// entry, in the order in which every UID first appears
+    pub fn merged_attribution(&self) -> Vec<EnergyConsumerAttribution> {
+        let mut merged = Vec::<EnergyConsumerAttribution>::with_capacity(self.attribution.len());
+        let mut index = HashMap::<i32, usize>::with_capacity(self.attribution.len());
+        for a in &self.attribution {
+            match index.entry(a.uid) {
+                hash_map::Entry::Occupied(i) => {
+                    let m = &mut merged[*i.get()];
+                    m.energy_uws = m.energy_uws.saturating_add(a.energy_uws);
+                }
+                hash_map::Entry::Vacant(i) => {
+                    i.insert(merged.len());
+                    merged.push(*a);
+                }
+            }
+        }
+        merged
+    }
+}
+
+/// Energy accumulated between two readings of the same meter or consumer
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EnergyDelta {
+    /// Monotonic timestamp since boot of the earlier reading
+    pub start: Duration,
+    /// Monotonic timestamp since boot of the later reading
+    pub end: Duration,
+    /// Energy in `uWs` (uJ) accumulated between [`Self::start`] and [`Self::end`].  Unlike absolute
+    /// readings this may legitimately be negative, when the counter wrapped or was reset in
+    /// between.
+    pub energy_uws: i64,
+}
+
+impl EnergyDelta {
+    /// Time between both readings, [`None`] if they were passed in the wrong order
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.end.checked_sub(self.start)
+    }
+
+    /// Average power in Watts over [`Self::elapsed()`], [`None`] if no (positive) time elapsed
+    pub fn watts(&self) -> Option<f64> {
+        self.elapsed()
+            .filter(|e| !e.is_zero())
+            .map(|e| self.energy_uws as f64 / 1_000_000.0 / e.as_secs_f64())
+    }
+
+    /// Rejects deltas that can't come from real hardware: time going backwards, energy accumulating
+    /// in zero time, or an average power above `max_watts`.
+    ///
+    /// A reasonable `max_watts` depends on the rail: an entire phone rarely sustains more than
+    /// 15W, big CPU clusters and GPUs peak somewhere around 5-10W, and display, radio and memory
+    /// rails tend to stay below 2-3W.
+    pub fn is_plausible(&self, max_watts: f64) -> bool {
+        match self.elapsed() {
+            None => false,
+            Some(e) if e.is_zero() => self.energy_uws == 0,
+            Some(_) => self.watts().is_some_and(|w| w <= max_watts),
+        }
+    }
+}
+
+/// [`EnergyConsumerAttribution::uid`] of the energy of all UIDs beyond
+/// [`PowerStats::with_attribution_limit()`]
+pub const OTHER_ATTRIBUTION_UID: i32 = -1;
+
 /// How much power a certain UID (app) consumed
 #[doc(alias = "android.hardware.power.stats.EnergyConsumerAttribution")]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -464,3 +1897,1322 @@ pub fn sample_gpu_meters() {
 
     sample_gpu_meters().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::*;
+    use android_hardware_power_stats::EnergyConsumerType as HalType;
+
+    #[test]
+    fn read_all_consumers_with_attribution() {
+        let (stats, calls) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 0, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(1, 1, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(2, 0, HalType::OTHER, "GPU"),
+                ])
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| {
+                        let e = 100 * (id as i64 + 1);
+                        consumer_result(id, 5000, e, &[(10001, e / 4), (10002, e / 2)])
+                    })
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let all = stats.read_all_consumers_with_attribution().unwrap();
+        assert_eq!(all.len(), 3);
+        for (i, (consumer, reading)) in all.iter().enumerate() {
+            assert_eq!(consumer.id, i as i32);
+            assert_eq!(reading.energy_uws, 100 * (i as i64 + 1));
+            assert_eq!(
+                reading.attribution,
+                [
+                    EnergyConsumerAttribution {
+                        uid: 10001,
+                        energy_uws: reading.energy_uws / 4
+                    },
+                    EnergyConsumerAttribution {
+                        uid: 10002,
+                        energy_uws: reading.energy_uws / 2
+                    },
+                ]
+            );
+        }
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("getEnergyConsumerInfo", vec![]),
+                ("getEnergyConsumed", vec![0, 1, 2])
+            ]
+        );
+    }
+
+    #[test]
+    fn read_all_consumers_by_id() {
+        let consumers = || {
+            Ok(vec![
+                consumer(0, 0, HalType::CPU_CLUSTER, "CPU"),
+                consumer(1, 0, HalType::DISPLAY, "DISPLAY"),
+                consumer(2, 0, HalType::OTHER, "GPU"),
+            ])
+        };
+        // Out of order and without the (invalid) display
+        let (stats, _) = MockPowerStats {
+            energy_consumer_info: Box::new(consumers),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .rev()
+                    .filter(|&&id| id != 1)
+                    .map(|&id| consumer_result(id, 5000, 100 * id as i64, &[]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let err = stats.read_all_consumers_with_attribution().unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&PowerStatsError::MissingReading(1))
+        );
+
+        let (stats, _) = MockPowerStats {
+            energy_consumer_info: Box::new(consumers),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .rev()
+                    .map(|&id| consumer_result(id, 5000, 100 * id as i64, &[]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        for (consumer, reading) in stats.read_all_consumers_with_attribution().unwrap() {
+            assert_eq!(reading.energy_uws, 100 * consumer.id as i64);
+        }
+    }
+
+    #[test]
+    fn read_energy_meters_by_deadline() {
+        let (stats, calls) = MockPowerStats {
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 1000, 5))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let err = stats
+            .read_energy_meters_by_deadline(&[0], Instant::now())
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&PowerStatsError::Timeout));
+        assert!(calls.lock().unwrap().is_empty());
+
+        let readings = stats
+            .read_energy_meters_by_deadline(&[0], Instant::now() + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(readings.len(), 1);
+    }
+
+    #[test]
+    fn energy_by_type() {
+        let (stats, _) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 0, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(1, 1, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(2, 0, HalType::OTHER, "GPU"),
+                    consumer(3, 0, HalType::DISPLAY, "DISPLAY"),
+                    consumer(4, 1, HalType::DISPLAY, "DISPLAY"),
+                ])
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| match id {
+                        0 => consumer_result(id, 5000, 300, &[]),
+                        1 => consumer_result(id, 5000, 500, &[]),
+                        2 => consumer_result(id, 5000, 70, &[]),
+                        _ => consumer_result(id, 5000, i64::MAX, &[]),
+                    })
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let totals = stats.energy_by_type().unwrap();
+        assert_eq!(
+            totals,
+            HashMap::from([
+                (EnergyConsumerType::CpuCluster, 800),
+                (EnergyConsumerType::Other, 70),
+                (EnergyConsumerType::Display, i64::MAX),
+            ])
+        );
+    }
+
+    #[test]
+    fn duplicate_meter_id() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                    channel(1, "S3M_VDD_CPUCL1", "CPU(MID)"),
+                    channel(0, "S4M_VDD_CPUCL0", "CPU(LITTLE)"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let err = stats.energy_meters().unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&PowerStatsError::DuplicateMeterId(0))
+        );
+    }
+
+    #[test]
+    fn subsystems() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                    channel(2, "L15M_VDD_SLC_M", "GPU"),
+                    channel(3, "S6M_LLDO1", "Display"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        assert_eq!(stats.subsystems().unwrap(), ["CPU(BIG)", "Display", "GPU"]);
+    }
+
+    #[test]
+    fn read_subsystem_meters() {
+        let (stats, calls) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                    channel(2, "L15M_VDD_SLC_M", "GPU"),
+                ])
+            }),
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 10, 100 * id as i64))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let gpu = stats.read_subsystem_meters("GPU").unwrap();
+        assert_eq!(
+            gpu.iter()
+                .map(|(m, r)| (m.name.as_str(), r.energy_uws))
+                .collect::<Vec<_>>(),
+            [("S9S_VDD_G3D", 0), ("L15M_VDD_SLC_M", 200)]
+        );
+        assert!(stats.read_subsystem_meters("Display").unwrap().is_empty());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("getEnergyMeterInfo", vec![]),
+                ("readEnergyMeter", vec![0, 2]),
+                ("getEnergyMeterInfo", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn permission_denied_on_discovery() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| Err(StatusCode::PERMISSION_DENIED.into())),
+            energy_consumer_info: Box::new(|| Err(ExceptionCode::SECURITY.into())),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let err = stats.energy_meters().unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&PowerStatsError::PermissionDenied {
+                method: "getEnergyMeterInfo"
+            })
+        );
+        let err = stats.energy_consumers().unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&PowerStatsError::PermissionDenied {
+                method: "getEnergyConsumerInfo"
+            })
+        );
+
+        // Anything else is left alone
+        let err = stats.read_energy_meters(&[0]).unwrap_err();
+        assert_eq!(err.downcast_ref::<PowerStatsError>(), None);
+    }
+
+    #[test]
+    fn energy_delta_plausibility() {
+        let delta = |start_ms, end_ms, energy_uws| EnergyDelta {
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+            energy_uws,
+        };
+
+        // 2J over 1s
+        assert_eq!(delta(1000, 2000, 2_000_000).watts(), Some(2.0));
+        assert!(delta(1000, 2000, 2_000_000).is_plausible(10.0));
+        assert!(delta(1000, 1000, 0).is_plausible(10.0));
+
+        // Time going backwards
+        assert!(!delta(2000, 1000, 2_000_000).is_plausible(10.0));
+        // Energy without elapsed time
+        assert!(!delta(1000, 1000, 1).is_plausible(10.0));
+        // 20W
+        assert!(!delta(1000, 2000, 20_000_000).is_plausible(10.0));
+    }
+
+    #[test]
+    fn read_energy_meters_map() {
+        let (stats, _) = MockPowerStats {
+            // Deliberately out of order
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .rev()
+                    .map(|&id| measurement(id, 1000, 1000, 10 * id as i64))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let readings = stats.read_energy_meters_map(&[4, 7, 9]).unwrap();
+        let mut ids = readings.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!(ids, [4, 7, 9]);
+        for (id, reading) in readings {
+            assert_eq!(reading.energy_uws, 10 * id as i64);
+        }
+    }
+
+    #[test]
+    fn duration_is_reliable() {
+        let meters = || Box::new(|| Ok(vec![channel(0, "S9S_VDD_G3D", "GPU")])) as _;
+
+        let start = Instant::now();
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: meters(),
+            read_energy_meter: Box::new(move |ids| {
+                let ms = start.elapsed().as_millis() as i64;
+                Ok(ids.iter().map(|&id| measurement(id, ms, ms, 0)).collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        assert!(stats.duration_is_reliable().unwrap());
+
+        for bogus_ms in [0, 1000] {
+            let (stats, _) = MockPowerStats {
+                energy_meter_info: meters(),
+                read_energy_meter: Box::new(move |ids| {
+                    let ms = start.elapsed().as_millis() as i64;
+                    Ok(ids
+                        .iter()
+                        .map(|&id| measurement(id, ms, bogus_ms, 0))
+                        .collect())
+                }),
+                ..Default::default()
+            }
+            .into_power_stats();
+            assert!(!stats.duration_is_reliable().unwrap());
+        }
+    }
+
+    #[test]
+    fn java_readings_receiver_is_reused() {
+        let service = MockPowerStatsService {
+            power_monitor_readings: Box::new(|ids| {
+                Some((
+                    vec![1000; ids.len()],
+                    ids.iter().map(|&id| id as i64).collect(),
+                ))
+            }),
+            ..Default::default()
+        };
+        let receivers = service.receivers.clone();
+        let (stats, _) = service.into_power_stats();
+
+        for _ in 0..3 {
+            let readings = stats.read_energy_meters(&[5]).unwrap();
+            assert_eq!(readings[0].energy_uws, 5);
+        }
+        let receivers = receivers.lock().unwrap();
+        assert_eq!(receivers.len(), 3);
+        assert!(receivers.iter().all(|r| *r == receivers[0]));
+    }
+
+    #[test]
+    fn java_readings_receiver_is_replaced_after_timeout() {
+        let service = MockPowerStatsService {
+            // Never respond to the first read
+            power_monitor_readings: Box::new(|ids| {
+                (ids != [0]).then(|| (vec![1000; ids.len()], vec![0; ids.len()]))
+            }),
+            ..Default::default()
+        };
+        let receivers = service.receivers.clone();
+        let (stats, _) = service.into_power_stats();
+
+        let err = stats
+            .read_energy_meters_by_deadline(&[0], Instant::now() + Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&PowerStatsError::Timeout));
+        stats.read_energy_meters(&[1]).unwrap();
+
+        let receivers = receivers.lock().unwrap();
+        assert_ne!(receivers[0], receivers[1]);
+    }
+
+    #[test]
+    fn read_energy_consumers_totals_only() {
+        let (stats, _) = MockPowerStats {
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 5000 + id as i64, 300, &[(10001, 100)]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let ids = [2, 0, 1];
+        let totals = stats.read_energy_consumers_totals_only(&ids).unwrap();
+        let full = stats.read_energy_consumers(&ids).unwrap();
+        assert_eq!(totals.len(), full.len());
+        for ((&id, total), reading) in ids.iter().zip(totals).zip(full) {
+            assert_eq!(total, (id, reading.timestamp, reading.energy_uws));
+        }
+    }
+
+    #[test]
+    fn consumer_backing_meters() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                    channel(2, "L2S_VDD_G3D_L2", "gpu"),
+                    channel(3, "S6M_LLDO1", "Display"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let consumer = |name: &str| EnergyConsumer {
+            id: 0,
+            name: name.to_string(),
+            ordinal: 0,
+            r#type: EnergyConsumerType::Other,
+            raw_type: 0,
+        };
+        let ids = |meters: Vec<EnergyMeter>| meters.iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(stats.consumer_backing_meters(&consumer("GPU")).unwrap()),
+            [0, 2]
+        );
+        assert_eq!(
+            ids(stats.consumer_backing_meters(&consumer("CPU")).unwrap()),
+            [1]
+        );
+        assert!(stats
+            .consumer_backing_meters(&consumer("WIFI"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn read_mixed_rails() {
+        let (stats, calls) = MockPowerStats {
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 1000, id as i64))
+                    .collect())
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000, -(id as i64), &[]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let readings = stats
+            .read(&[
+                RailRef::Consumer(1),
+                RailRef::Meter(2),
+                RailRef::Meter(3),
+                RailRef::Consumer(4),
+            ])
+            .unwrap();
+        assert!(matches!(
+            readings.as_slice(),
+            [
+                EnergyReading::Consumer(EnergyConsumerReading { energy_uws: -1, .. }),
+                EnergyReading::Meter(EnergyMeterReading { energy_uws: 2, .. }),
+                EnergyReading::Meter(EnergyMeterReading { energy_uws: 3, .. }),
+                EnergyReading::Consumer(EnergyConsumerReading { energy_uws: -4, .. }),
+            ]
+        ));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("readEnergyMeter", vec![2, 3]),
+                ("getEnergyConsumed", vec![1, 4])
+            ]
+        );
+
+        // The HAL leaves out an invalid meter
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .filter(|&&id| id != 3)
+                    .map(|&id| measurement(id, 1000, 1000, id as i64))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let err = stats
+            .read(&[RailRef::Meter(2), RailRef::Meter(3)])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&PowerStatsError::MissingReading(3))
+        );
+    }
+
+    #[test]
+    fn negative_energy_is_invalid() {
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 1000, if id == 1 { -5 } else { 5 }))
+                    .collect())
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000, if id == 1 { i64::MIN } else { 0 }, &[]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let meters = stats.read_energy_meters(&[0, 1]).unwrap();
+        assert!(meters[0].is_valid());
+        assert!(!meters[1].is_valid());
+        let consumers = stats.read_energy_consumers(&[0, 1]).unwrap();
+        assert!(consumers[0].is_valid());
+        assert!(!consumers[1].is_valid());
+    }
+
+    #[test]
+    fn java_service_returned_error() {
+        use android_os_powerstatsservice::powerstatsservice::RESULT_UNSUPPORTED_POWER_MONITOR;
+
+        let (stats, _) = MockPowerStatsService {
+            result_code: RESULT_UNSUPPORTED_POWER_MONITOR,
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        for err in [
+            stats.energy_meters().unwrap_err(),
+            stats.read_energy_meters(&[0]).unwrap_err(),
+        ] {
+            assert_eq!(
+                err.downcast_ref(),
+                Some(&PowerStatsError::ServiceReturnedError(
+                    RESULT_UNSUPPORTED_POWER_MONITOR
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_bundle() {
+        use android_os_powerstatsservice::powerstatsservice::{KEY_ENERGY, KEY_MONITORS};
+
+        for key in [KEY_MONITORS, KEY_ENERGY] {
+            let (stats, _) = MockPowerStatsService {
+                supported_power_monitors: Box::new(|| {
+                    vec![monitor(
+                        0,
+                        PowerMonitorType::Measurement,
+                        "[S9S_VDD_G3D]:GPU",
+                    )]
+                }),
+                power_monitor_readings: Box::new(|ids| {
+                    Some((vec![1000; ids.len()], vec![300; ids.len()]))
+                }),
+                omit_key: Some(key),
+                ..Default::default()
+            }
+            .into_power_stats();
+            let err = match key {
+                KEY_MONITORS => stats.energy_meters().unwrap_err(),
+                _ => stats.read_energy_meters(&[0]).unwrap_err(),
+            };
+            assert_eq!(
+                err.downcast_ref(),
+                Some(&PowerStatsError::MalformedBundle { key })
+            );
+        }
+    }
+
+    #[test]
+    fn meter_by_stable_id() {
+        let stable_id = EnergyMeter::stable_id_of("S9S_VDD_G3D", "GPU");
+        assert_eq!(stable_id, EnergyMeter::stable_id_of("S9S_VDD_G3D", "GPU"));
+        assert_ne!(stable_id, EnergyMeter::stable_id_of("S9S_VDD_G3", "DGPU"));
+        assert_ne!(stable_id, EnergyMeter::stable_id_of("S9S_VDD_G3D", "SoC"));
+
+        // Renumbered after a reboot
+        for id in [0, 7] {
+            let (stats, _) = MockPowerStats {
+                energy_meter_info: Box::new(move || {
+                    Ok(vec![
+                        channel(id + 1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                        channel(id, "S9S_VDD_G3D", "GPU"),
+                    ])
+                }),
+                ..Default::default()
+            }
+            .into_power_stats();
+
+            let meter = stats.meter_by_stable_id(stable_id).unwrap().unwrap();
+            assert_eq!((meter.id, meter.name.as_str()), (id, "S9S_VDD_G3D"));
+            assert_eq!(stats.meter_by_stable_id(1234).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn merged_attribution() {
+        let reading = EnergyConsumerReading {
+            timestamp: Duration::from_secs(1),
+            energy_uws: 600,
+            attribution: [(10123, 100), (1000, 200), (10123, 300)]
+                .map(|(uid, energy_uws)| EnergyConsumerAttribution { uid, energy_uws })
+                .to_vec(),
+        };
+        assert_eq!(
+            reading.merged_attribution(),
+            [
+                EnergyConsumerAttribution {
+                    uid: 10123,
+                    energy_uws: 400
+                },
+                EnergyConsumerAttribution {
+                    uid: 1000,
+                    energy_uws: 200
+                },
+            ]
+        );
+        // The raw entries are left alone
+        assert_eq!(reading.attribution.len(), 3);
+
+        let reading = EnergyConsumerReading {
+            attribution: [(10123, i64::MAX), (10123, 1)]
+                .map(|(uid, energy_uws)| EnergyConsumerAttribution { uid, energy_uws })
+                .to_vec(),
+            ..reading
+        };
+        assert_eq!(reading.merged_attribution()[0].energy_uws, i64::MAX);
+    }
+
+    #[test]
+    fn canonical_consumer_names() {
+        let (vendor, _) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 0, HalType::CPU_CLUSTER, "CPUCL0"),
+                    consumer(1, 1, HalType::CPU_CLUSTER, "CPUCL1"),
+                    consumer(2, 0, HalType::OTHER, "GPU"),
+                    consumer(3, 0, HalType::MOBILE_RADIO, "MODEM"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let (java, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                ["CPU/0", "CPU/1", "GPU", "MOBILE_RADIO"]
+                    .into_iter()
+                    .zip(10..)
+                    .map(|(name, index)| monitor(index, PowerMonitorType::Consumer, name))
+                    .collect()
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let canonical = |stats: &PowerStats| {
+            stats
+                .energy_consumers()
+                .unwrap()
+                .iter()
+                .map(|c| c.canonical_name())
+                .collect::<Vec<_>>()
+        };
+        let expected = ["cpu_cluster_0", "cpu_cluster_1", "gpu_0", "mobile_radio_0"];
+        assert_eq!(canonical(&vendor), expected);
+        assert_eq!(canonical(&java), expected);
+
+        let other = EnergyConsumer {
+            id: 0,
+            name: "  L2S VDD/G3D--L2 ".to_string(),
+            ordinal: 2,
+            r#type: EnergyConsumerType::Other,
+            raw_type: 0,
+        };
+        assert_eq!(other.canonical_name(), "l2s_vdd_g3d_l2_2");
+    }
+
+    #[test]
+    fn partial_reads() {
+        const VALID: [i32; 2] = [0, 1];
+        // One HAL leaves invalid ids out, the other fails the whole call
+        for strict in [false, true] {
+            let (stats, calls) = MockPowerStats {
+                read_energy_meter: Box::new(move |ids| {
+                    if strict && ids.iter().any(|id| !VALID.contains(id)) {
+                        return Err(ExceptionCode::ILLEGAL_ARGUMENT.into());
+                    }
+                    Ok(ids
+                        .iter()
+                        .filter(|id| VALID.contains(id))
+                        .map(|&id| measurement(id, 1000, 0, 100 * id as i64))
+                        .collect())
+                }),
+                energy_consumed: Box::new(move |ids| {
+                    if strict && ids.iter().any(|id| !VALID.contains(id)) {
+                        return Err(ExceptionCode::ILLEGAL_ARGUMENT.into());
+                    }
+                    Ok(ids
+                        .iter()
+                        .filter(|id| VALID.contains(id))
+                        .map(|&id| consumer_result(id, 1000, 100 * id as i64, &[]))
+                        .collect())
+                }),
+                ..Default::default()
+            }
+            .into_power_stats();
+
+            let readings = stats.read_energy_meters_partial(&[1, 5, 0]).unwrap();
+            assert_eq!(readings[0].as_ref().unwrap().energy_uws, 100);
+            assert_eq!(readings[1], Err(PowerStatsError::MissingReading(5)));
+            assert_eq!(readings[2].as_ref().unwrap().energy_uws, 0);
+
+            let readings = stats.read_energy_consumers_partial(&[5, 1]).unwrap();
+            assert_eq!(readings[0], Err(PowerStatsError::MissingReading(5)));
+            assert_eq!(readings[1].as_ref().unwrap().energy_uws, 100);
+
+            // Only the strict HAL needs to be asked for every id separately
+            let reads = calls.lock().unwrap().len();
+            assert_eq!(reads, if strict { 2 + 3 + 2 } else { 2 });
+        }
+    }
+
+    #[test]
+    fn consumers_of_type_ordered() {
+        let (vendor, _) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 2, HalType::CPU_CLUSTER, "CPUCL2"),
+                    consumer(1, 0, HalType::OTHER, "GPU"),
+                    consumer(2, 0, HalType::CPU_CLUSTER, "CPUCL0"),
+                    consumer(3, 1, HalType::CPU_CLUSTER, "CPUCL1"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let (java, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                ["CPU/1", "GPU", "CPU/2", "CPU/0"]
+                    .into_iter()
+                    .zip(0..)
+                    .map(|(name, index)| monitor(index, PowerMonitorType::Consumer, name))
+                    .collect()
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        for (stats, ids) in [(vendor, [2, 3, 0]), (java, [3, 0, 2])] {
+            let clusters = stats
+                .consumers_of_type_ordered(EnergyConsumerType::CpuCluster)
+                .unwrap();
+            assert_eq!(
+                clusters.iter().map(|c| c.ordinal).collect::<Vec<_>>(),
+                [0, 1, 2]
+            );
+            assert_eq!(clusters.iter().map(|c| c.id).collect::<Vec<_>>(), ids);
+        }
+    }
+
+    #[test]
+    fn connect_fallback() {
+        let (info, selections) = {
+            let selections = Mutex::new(vec![]);
+            let (_, info) = PowerStats::connect_with(|selection| {
+                selections.lock().unwrap().push(selection);
+                match selection {
+                    BackendSelection::VendorHardwareService => {
+                        Ok(MockPowerStats::default().into_power_stats().0)
+                    }
+                    _ => Err(StatusCode::NAME_NOT_FOUND.into()),
+                }
+            })
+            .unwrap();
+            (info, selections.into_inner().unwrap())
+        };
+        assert_eq!(
+            selections,
+            [
+                BackendSelection::SystemJavaService,
+                BackendSelection::VendorHardwareService
+            ]
+        );
+        assert_eq!(
+            info,
+            ConnectionInfo {
+                backend: BackendSelection::VendorHardwareService,
+                descriptor: "android.hardware.power.stats.IPowerStats".to_string(),
+                // The mock predates versioning
+                hal_version: Some(1),
+                fallback_used: true,
+            }
+        );
+
+        let (_, info) =
+            PowerStats::connect_with(|_| Ok(MockPowerStatsService::default().into_power_stats().0))
+                .unwrap();
+        assert_eq!(info.backend, BackendSelection::SystemJavaService);
+        assert_eq!(info.hal_version, None);
+        assert!(!info.fallback_used);
+    }
+
+    #[test]
+    fn attribution_table() {
+        let (stats, _) = MockPowerStats {
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| match id {
+                        0 => consumer_result(id, 1000, 600, &[(10123, 100), (1000, 500)]),
+                        _ => consumer_result(id, 1000, i64::MAX, &[(10123, i64::MAX), (10456, 7)]),
+                    })
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let table = stats.read_consumers_attribution_table(&[0, 1]).unwrap();
+        assert_eq!(
+            table,
+            BTreeMap::from([(1000, 500), (10123, i64::MAX), (10456, 7)])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`durationMs` of the reading for id 3 is negative: -5ms")]
+    fn negative_duration() {
+        let _ = EnergyMeterReading::from(measurement(3, 1000, -5, 0));
+    }
+
+    #[test]
+    fn clones_share_monitors() {
+        let names = Arc::new(Mutex::new(vec!["[S9S_VDD_G3D]:GPU"]));
+        let (stats, calls) = MockPowerStatsService {
+            supported_power_monitors: Box::new({
+                let names = Arc::clone(&names);
+                move || {
+                    names
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .zip(0..)
+                        .map(|(name, index)| monitor(index, PowerMonitorType::Measurement, name))
+                        .collect()
+                }
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let discoveries = || {
+            calls
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(method, _)| *method == "getSupportedPowerMonitors")
+                .count()
+        };
+
+        let clone = stats.clone();
+        assert_eq!(stats.energy_meters().unwrap().len(), 1);
+        assert_eq!(clone.energy_meters().unwrap().len(), 1);
+        assert_eq!(clone.energy_consumers().unwrap(), []);
+        assert_eq!(discoveries(), 1);
+
+        // Refreshing either one is visible to the other
+        names.lock().unwrap().push("[S2M_VDD_CPUCL2]:CPU(BIG)");
+        clone.refresh().unwrap();
+        assert_eq!(stats.energy_meters().unwrap().len(), 2);
+        assert_eq!(discoveries(), 2);
+    }
+
+    #[test]
+    fn interpolate() {
+        let a = EnergyMeterReading::from(measurement(0, 1000, 0, 1000));
+        let b = EnergyMeterReading::from(measurement(0, 1100, 0, 3000));
+        let at = |ms| EnergyMeterReading::interpolate(&a, &b, Duration::from_millis(ms));
+        assert_eq!(at(1050), Some(2000.0));
+        assert_eq!(at(1000), Some(1000.0));
+        assert_eq!(at(1100), Some(3000.0));
+        assert_eq!(at(999), None);
+        assert_eq!(at(1101), None);
+        // Wrong order
+        assert_eq!(
+            EnergyMeterReading::interpolate(&b, &a, Duration::from_millis(1050)),
+            None
+        );
+    }
+
+    #[test]
+    fn attribution_backfill() {
+        let (stats, _) = MockPowerStats {
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| match id {
+                        // Zero total with attribution
+                        0 => consumer_result(id, 1000, 0, &[(10123, 100), (1000, 200)]),
+                        // Legitimately idle
+                        1 => consumer_result(id, 1000, 0, &[]),
+                        _ => consumer_result(id, 1000, 500, &[(10123, 100)]),
+                    })
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let totals = |stats: &PowerStats| {
+            stats
+                .read_energy_consumers(&[0, 1, 2])
+                .unwrap()
+                .iter()
+                .map(|r| r.energy_uws)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(totals(&stats), [0, 0, 500]);
+
+        let stats = stats.with_attribution_backfill(true);
+        assert_eq!(totals(&stats), [300, 0, 500]);
+        let partial = stats.read_energy_consumers_partial(&[0]).unwrap();
+        assert_eq!(partial[0].as_ref().unwrap().energy_uws, 300);
+    }
+
+    #[test]
+    fn duplicate_meter_ids() {
+        let (stats, calls) = MockPowerStats {
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 10, 100 * id as i64))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let readings = stats.read_energy_meters(&[5, 5, 3]).unwrap();
+        assert_eq!(
+            readings.iter().map(|r| r.energy_uws).collect::<Vec<_>>(),
+            [500, 500, 300]
+        );
+        let readings = stats.read_energy_meters_map(&[5, 5, 3]).unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("readEnergyMeter", vec![5, 3]),
+                ("readEnergyMeter", vec![5, 3])
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_meter_ids_timeout() {
+        let (stats, calls) = MockPowerStatsService {
+            power_monitor_readings: Box::new(|ids| {
+                Some((
+                    vec![1000; ids.len()],
+                    ids.iter().map(|&id| 100 * id as i64).collect(),
+                ))
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let readings = stats
+            .read_energy_meters_timeout(&[5, 5, 3], Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            readings.iter().map(|r| r.energy_uws).collect::<Vec<_>>(),
+            [500, 500, 300]
+        );
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [("getPowerMonitorReadings", vec![5, 3])]
+        );
+    }
+
+    #[test]
+    fn raw_consumer_types() {
+        let (vendor, _) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 0, HalType::CPU_CLUSTER, "CPUCL0"),
+                    // Not (yet) known to this crate
+                    consumer(1, 0, HalType(42), "NPU"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let types = |stats: &PowerStats| {
+            stats
+                .energy_consumers()
+                .unwrap()
+                .iter()
+                .map(|c| (c.r#type, c.raw_type))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            types(&vendor),
+            [
+                (EnergyConsumerType::CpuCluster, 2),
+                (EnergyConsumerType::Other, 42)
+            ]
+        );
+
+        let (java, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                vec![
+                    monitor(10, PowerMonitorType::Consumer, "CPU/0"),
+                    monitor(11, PowerMonitorType::Consumer, "GPU"),
+                ]
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        assert_eq!(
+            types(&java),
+            [
+                (EnergyConsumerType::CpuCluster, 2),
+                (EnergyConsumerType::Other, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn hal_version() {
+        // The generated service, like HALs from before versioning, doesn't know the transaction
+        let (stats, _) = MockPowerStats::default().into_power_stats();
+        assert_eq!(stats.hal_version().unwrap(), 1);
+
+        let (stats, _) = MockPowerStatsService::default().into_power_stats();
+        assert!(matches!(
+            stats.hal_version().unwrap_err().downcast_ref(),
+            Some(PowerStatsError::Unsupported {
+                method: "getInterfaceVersion"
+            })
+        ));
+    }
+
+    #[test]
+    fn unknown_power_monitor_type() {
+        let (stats, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                vec![
+                    monitor(0, PowerMonitorType::Consumer, "GPU"),
+                    monitor(1, PowerMonitorType::Measurement, "[S9S_VDD_G3D]:GPU"),
+                    // Neither name format applies
+                    monitor(2, PowerMonitorType::Unknown(2), "VSYS_PWR_NPU"),
+                ]
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let meters = stats.energy_meters().unwrap();
+        assert_eq!(meters.iter().map(|m| m.id).collect::<Vec<_>>(), [1]);
+        let consumers = stats.energy_consumers().unwrap();
+        assert_eq!(consumers.iter().map(|c| c.id).collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn attribution_limit() {
+        let (stats, _) = MockPowerStats {
+            energy_consumed: Box::new(|ids| {
+                let attribution = (0..1000)
+                    .map(|i| (10_000 + i, i as i64))
+                    .collect::<Vec<_>>();
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000, 499_500, &attribution))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let unlimited = stats.read_energy_consumers(&[0]).unwrap();
+        assert_eq!(unlimited[0].attribution.len(), 1000);
+
+        let stats = stats.with_attribution_limit(Some(3));
+        let reading = &stats.read_energy_consumers(&[0]).unwrap()[0];
+        assert_eq!(
+            reading
+                .attribution
+                .iter()
+                .map(|a| (a.uid, a.energy_uws))
+                .collect::<Vec<_>>(),
+            [
+                (10_999, 999),
+                (10_998, 998),
+                (10_997, 997),
+                // Everything else, so that the attribution still adds up
+                (OTHER_ATTRIBUTION_UID, 499_500 - 999 - 998 - 997),
+            ]
+        );
+        assert_eq!(reading.energy_uws, 499_500);
+    }
+
+    #[test]
+    fn reconnect() {
+        let (mut stats, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                vec![monitor(
+                    0,
+                    PowerMonitorType::Measurement,
+                    "[S9S_VDD_G3D]:GPU",
+                )]
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        assert_eq!(stats.connection_generation(), 0);
+        assert_eq!(stats.energy_meters().unwrap().len(), 1);
+
+        // The restarted service lost a rail, which must not be served from the previous discovery
+        stats
+            .reconnect_with(|selection| {
+                assert_eq!(selection, BackendSelection::SystemJavaService);
+                Ok(MockPowerStatsService::default().into_power_stats().0)
+            })
+            .unwrap();
+        assert_eq!(stats.connection_generation(), 1);
+        assert!(stats.energy_meters().unwrap().is_empty());
+        assert_eq!(stats.clone().connection_generation(), 1);
+
+        // A failed attempt keeps the current connection
+        stats
+            .reconnect_with(|_| Err(StatusCode::DEAD_OBJECT.into()))
+            .unwrap_err();
+        assert_eq!(stats.connection_generation(), 1);
+    }
+
+    #[test]
+    fn malformed_monitor_names() {
+        let (stats, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                vec![
+                    monitor(0, PowerMonitorType::Measurement, "[S9S_VDD_G3D]:GPU"),
+                    monitor(1, PowerMonitorType::Measurement, "VSYS_PWR_DISPLAY"),
+                    monitor(2, PowerMonitorType::Measurement, "S2M_VDD_CPUCL2]:CPU(BIG)"),
+                    monitor(3, PowerMonitorType::Consumer, "CPU/1"),
+                    monitor(4, PowerMonitorType::Consumer, "CPU/big"),
+                    monitor(5, PowerMonitorType::Consumer, "GPU"),
+                ]
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let meters = stats.energy_meters().unwrap();
+        assert_eq!(meters.iter().map(|m| m.id).collect::<Vec<_>>(), [0]);
+        let consumers = stats.energy_consumers().unwrap();
+        assert_eq!(consumers.iter().map(|c| c.id).collect::<Vec<_>>(), [3, 5]);
+
+        assert_eq!(
+            consumer_from_monitor(monitor(4, PowerMonitorType::Consumer, "CPU/big")),
+            Err(PowerStatsError::MalformedMonitorName {
+                raw: "CPU/big".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn energy_breakdown() {
+        let (stats, _) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 0, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(1, 0, HalType::DISPLAY, "DISPLAY"),
+                    consumer(2, 0, HalType::OTHER, "GPU"),
+                ])
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| match id {
+                        0 => consumer_result(id, 1000, 500, &[(10123, 100), (10456, 400)]),
+                        1 => consumer_result(id, 1000, 500, &[(10456, 500)]),
+                        _ => consumer_result(id, 1000, 900, &[(10123, 300), (10456, 600)]),
+                    })
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let breakdown = stats.energy_breakdown_of(10123).unwrap();
+        assert_eq!(
+            breakdown
+                .iter()
+                .map(|(c, e)| (c.name.as_str(), *e))
+                .collect::<Vec<_>>(),
+            [("GPU", 300), ("CPU", 100)]
+        );
+        assert!(stats.energy_breakdown_of(10789).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_energy_consumers_timeout() {
+        // Never respond
+        let (stats, _) = MockPowerStatsService::default().into_power_stats();
+        let err = stats
+            .read_energy_consumers_timeout(&[0], Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&PowerStatsError::Timeout));
+    }
+
+    #[test]
+    fn timeout_isnt_held_up_by_concurrent_read() {
+        // Never respond to reads of meter 0
+        let (stats, calls) = MockPowerStatsService {
+            power_monitor_readings: Box::new(|ids| {
+                (!ids.contains(&0)).then(|| (vec![1000; ids.len()], vec![0; ids.len()]))
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        std::thread::scope(|s| {
+            let hung = s.spawn(|| {
+                stats.read_energy_meters_by_deadline(&[0], Instant::now() + Duration::from_secs(1))
+            });
+            while calls.lock().unwrap().is_empty() {
+                std::thread::yield_now();
+            }
+
+            let start = Instant::now();
+            let err = stats
+                .read_energy_meters_timeout(&[0], Duration::from_millis(10))
+                .unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&PowerStatsError::Timeout));
+            let err = stats
+                .read_energy_consumers_timeout(&[0], Duration::from_millis(10))
+                .unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&PowerStatsError::Timeout));
+            stats.read_energy_meters(&[1]).unwrap();
+            assert!(start.elapsed() < Duration::from_millis(500));
+
+            let err = hung.join().unwrap().unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&PowerStatsError::Timeout));
+        });
+    }
+
+    #[test]
+    fn dropped_monitors_receiver() {
+        let (stats, _) = MockPowerStatsService {
+            drop_receivers: true,
+            ..Default::default()
+        }
+        .into_power_stats();
+        // Fails rather than waiting forever
+        let err = stats.refresh().unwrap_err();
+        assert!(err.to_string().contains("dropped"), "{err:#}");
+    }
+
+    #[test]
+    fn discovery_doesnt_overwrite_refresh() {
+        let stats = Arc::new(std::sync::OnceLock::<PowerStats>::new());
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let (java, _) = MockPowerStatsService {
+            supported_power_monitors: Box::new({
+                let stats = Arc::clone(&stats);
+                move || {
+                    match calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed) {
+                        // A refresh() completes while the lazy discovery is still waiting
+                        0 => {
+                            stats.get().unwrap().refresh().unwrap();
+                            vec![monitor(0, PowerMonitorType::Measurement, "[STALE]:GPU")]
+                        }
+                        _ => vec![monitor(0, PowerMonitorType::Measurement, "[FRESH]:GPU")],
+                    }
+                }
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let _ = stats.set(java.clone());
+
+        let rails =
+            |meters: Vec<EnergyMeter>| meters.into_iter().map(|m| m.name).collect::<Vec<_>>();
+        assert_eq!(rails(java.energy_meters().unwrap()), ["FRESH"]);
+        assert_eq!(rails(java.energy_meters().unwrap()), ["FRESH"]);
+    }
+}
@@ -0,0 +1,316 @@
+//! In-process stand-ins for the binder services, so that [`PowerStats`] can be exercised without a
+//! device.  Every HAL method is a boxed closure that tests override as needed, the remainder
+//! respond with `UNKNOWN_TRANSACTION` like a HAL that doesn't implement them.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use binder::{BinderFeatures, SpIBinder, StatusCode};
+
+use crate::{
+    android_hardware_power_stats::{
+        BnPowerStats, Channel, EnergyConsumer, EnergyConsumerAttribution, EnergyConsumerResult,
+        EnergyConsumerType, EnergyMeasurement, IPowerStats, PowerEntity, State,
+        StateResidencyResult,
+    },
+    android_os_powerstatsservice::{
+        powerstatsservice::{
+            BnPowerStatsService, KEY_ENERGY, KEY_MONITORS, KEY_TIMESTAMPS, RESULT_SUCCESS,
+        },
+        IPowerStatsService, PowerMonitor, PowerMonitorType,
+    },
+    bundle::{Bundle, Object},
+    mangled::_7_android_2_os_14_ResultReceiver as ResultReceiver,
+    Backend, PowerStats,
+};
+
+type Info<T> = Box<dyn Fn() -> binder::Result<Vec<T>> + Send + Sync>;
+type Read<T> = Box<dyn Fn(&[i32]) -> binder::Result<Vec<T>> + Send + Sync>;
+
+fn unimplemented_info<T>() -> Info<T> {
+    Box::new(|| Err(StatusCode::UNKNOWN_TRANSACTION.into()))
+}
+
+fn unimplemented_read<T>() -> Read<T> {
+    Box::new(|_| Err(StatusCode::UNKNOWN_TRANSACTION.into()))
+}
+
+/// What a null `resultData` is deserialized into, as the Java service sends it with every error
+/// code
+fn null_bundle() -> Bundle {
+    Bundle(HashMap::new())
+}
+
+/// Method name and ids of every call that reached the mock, in order
+pub(crate) type CallLog = Arc<Mutex<Vec<(&'static str, Vec<i32>)>>>;
+
+pub(crate) struct MockPowerStats {
+    pub(crate) power_entity_info: Info<PowerEntity>,
+    pub(crate) state_residency: Read<StateResidencyResult>,
+    pub(crate) energy_consumer_info: Info<EnergyConsumer>,
+    pub(crate) energy_consumed: Read<EnergyConsumerResult>,
+    pub(crate) energy_meter_info: Info<Channel>,
+    pub(crate) read_energy_meter: Read<EnergyMeasurement>,
+    pub(crate) calls: CallLog,
+}
+
+impl Default for MockPowerStats {
+    fn default() -> Self {
+        Self {
+            power_entity_info: unimplemented_info(),
+            state_residency: unimplemented_read(),
+            energy_consumer_info: unimplemented_info(),
+            energy_consumed: unimplemented_read(),
+            energy_meter_info: unimplemented_info(),
+            read_energy_meter: unimplemented_read(),
+            calls: Default::default(),
+        }
+    }
+}
+
+impl MockPowerStats {
+    /// Wraps the mock in a local binder and hands out a [`PowerStats`] talking to it, together
+    /// with the log of calls it receives
+    pub(crate) fn into_power_stats(self) -> (PowerStats, CallLog) {
+        let calls = self.calls.clone();
+        let binder = BnPowerStats::new_binder(self, BinderFeatures::default());
+        (
+            PowerStats::from_backend(Backend::VendorHardwareService(binder)),
+            calls,
+        )
+    }
+
+    fn log(&self, method: &'static str, ids: &[i32]) {
+        self.calls.lock().unwrap().push((method, ids.to_vec()));
+    }
+}
+
+impl binder::Interface for MockPowerStats {}
+
+#[allow(non_snake_case)]
+impl IPowerStats for MockPowerStats {
+    fn getPowerEntityInfo(&self) -> binder::Result<Vec<PowerEntity>> {
+        self.log("getPowerEntityInfo", &[]);
+        (self.power_entity_info)()
+    }
+
+    fn getStateResidency(&self, ids: &[i32]) -> binder::Result<Vec<StateResidencyResult>> {
+        self.log("getStateResidency", ids);
+        (self.state_residency)(ids)
+    }
+
+    fn getEnergyConsumerInfo(&self) -> binder::Result<Vec<EnergyConsumer>> {
+        self.log("getEnergyConsumerInfo", &[]);
+        (self.energy_consumer_info)()
+    }
+
+    fn getEnergyConsumed(&self, ids: &[i32]) -> binder::Result<Vec<EnergyConsumerResult>> {
+        self.log("getEnergyConsumed", ids);
+        (self.energy_consumed)(ids)
+    }
+
+    fn getEnergyMeterInfo(&self) -> binder::Result<Vec<Channel>> {
+        self.log("getEnergyMeterInfo", &[]);
+        (self.energy_meter_info)()
+    }
+
+    fn readEnergyMeter(&self, ids: &[i32]) -> binder::Result<Vec<EnergyMeasurement>> {
+        self.log("readEnergyMeter", ids);
+        (self.read_energy_meter)(ids)
+    }
+}
+
+pub(crate) fn channel(id: i32, name: &str, subsystem: &str) -> Channel {
+    Channel {
+        id,
+        name: name.to_string(),
+        subsystem: subsystem.to_string(),
+    }
+}
+
+/// States are numbered in the order of `states`
+pub(crate) fn entity(id: i32, name: &str, states: &[&str]) -> PowerEntity {
+    PowerEntity {
+        id,
+        name: name.to_string(),
+        states: states
+            .iter()
+            .zip(0..)
+            .map(|(name, id)| State {
+                id,
+                name: name.to_string(),
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn consumer(
+    id: i32,
+    ordinal: i32,
+    r#type: EnergyConsumerType,
+    name: &str,
+) -> EnergyConsumer {
+    EnergyConsumer {
+        id,
+        ordinal,
+        r#type,
+        name: name.to_string(),
+    }
+}
+
+pub(crate) fn measurement(
+    id: i32,
+    timestamp_ms: i64,
+    duration_ms: i64,
+    energy_uws: i64,
+) -> EnergyMeasurement {
+    EnergyMeasurement {
+        id,
+        timestampMs: timestamp_ms,
+        durationMs: duration_ms,
+        energyUWs: energy_uws,
+    }
+}
+
+/// `attribution` lists `(uid, energy_uws)` pairs
+pub(crate) fn consumer_result(
+    id: i32,
+    timestamp_ms: i64,
+    energy_uws: i64,
+    attribution: &[(i32, i64)],
+) -> EnergyConsumerResult {
+    EnergyConsumerResult {
+        id,
+        timestampMs: timestamp_ms,
+        energyUWs: energy_uws,
+        attribution: attribution
+            .iter()
+            .map(|&(uid, energy_uws)| EnergyConsumerAttribution {
+                uid,
+                energyUWs: energy_uws,
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn monitor(index: i32, r#type: PowerMonitorType, name: &str) -> PowerMonitor {
+    PowerMonitor {
+        index,
+        r#type,
+        name: name.to_string(),
+    }
+}
+
+/// `(timestamps_ms, energy_uws)` for the requested ids, or [`None`] to never call back
+type Readings = Box<dyn Fn(&[i32]) -> Option<(Vec<i64>, Vec<i64>)> + Send + Sync>;
+
+/// Stand-in for the Java `powerstats` service, which responds through the passed
+/// [`ResultReceiver`] rather than with a return value
+pub(crate) struct MockPowerStatsService {
+    pub(crate) supported_power_monitors: Box<dyn Fn() -> Vec<PowerMonitor> + Send + Sync>,
+    pub(crate) power_monitor_readings: Readings,
+    /// `resultCode` passed to every receiver, where anything but `0` signals an error
+    pub(crate) result_code: i32,
+    pub(crate) calls: CallLog,
+    /// Every receiver that was passed in, in order
+    pub(crate) receivers: Arc<Mutex<Vec<SpIBinder>>>,
+    /// Drop every receiver without calling back, like a oneway call that SELinux denied
+    pub(crate) drop_receivers: bool,
+    /// Leave this key out of every successful result, like a service that changed its format
+    pub(crate) omit_key: Option<&'static str>,
+}
+
+impl Default for MockPowerStatsService {
+    fn default() -> Self {
+        Self {
+            supported_power_monitors: Box::new(Vec::new),
+            power_monitor_readings: Box::new(|_| None),
+            result_code: 0,
+            calls: Default::default(),
+            receivers: Default::default(),
+            drop_receivers: false,
+            omit_key: None,
+        }
+    }
+}
+
+impl MockPowerStatsService {
+    pub(crate) fn into_power_stats(self) -> (PowerStats, CallLog) {
+        let calls = self.calls.clone();
+        let binder = BnPowerStatsService::new_binder(self, BinderFeatures::default());
+        (
+            PowerStats::from_backend(Backend::SystemJavaService(binder)),
+            calls,
+        )
+    }
+
+    fn omit(&self, mut bundle: Bundle) -> Bundle {
+        if let Some(key) = self.omit_key {
+            bundle.0.remove(key);
+        }
+        bundle
+    }
+
+    fn log(&self, method: &'static str, ids: &[i32], receiver: &ResultReceiver) {
+        self.calls.lock().unwrap().push((method, ids.to_vec()));
+        if self.drop_receivers {
+            return;
+        }
+        self.receivers
+            .lock()
+            .unwrap()
+            .push(receiver.binder().as_binder());
+    }
+}
+
+impl binder::Interface for MockPowerStatsService {}
+
+#[allow(non_snake_case)]
+impl IPowerStatsService for MockPowerStatsService {
+    fn getSupportedPowerMonitors(&self, receiver: &ResultReceiver) -> binder::Result<()> {
+        self.log("getSupportedPowerMonitors", &[], receiver);
+        if self.drop_receivers {
+            return Ok(());
+        }
+        if self.result_code != RESULT_SUCCESS {
+            return receiver.binder().r#send(self.result_code, &null_bundle());
+        }
+        let monitors = (self.supported_power_monitors)()
+            .into_iter()
+            .map(|pm| Box::new(pm) as _)
+            .collect();
+        let bundle = Bundle(HashMap::from([(
+            KEY_MONITORS.to_string(),
+            Object::ParcelableArray(monitors),
+        )]));
+        receiver
+            .binder()
+            .r#send(self.result_code, &self.omit(bundle))
+    }
+
+    fn getPowerMonitorReadings(
+        &self,
+        ids: &[i32],
+        receiver: &ResultReceiver,
+    ) -> binder::Result<()> {
+        self.log("getPowerMonitorReadings", ids, receiver);
+        if self.drop_receivers {
+            return Ok(());
+        }
+        if self.result_code != RESULT_SUCCESS {
+            return receiver.binder().r#send(self.result_code, &null_bundle());
+        }
+        let Some((timestamps, energy)) = (self.power_monitor_readings)(ids) else {
+            return Ok(());
+        };
+        let bundle = Bundle(HashMap::from([
+            (KEY_TIMESTAMPS.to_string(), Object::LongArray(timestamps)),
+            (KEY_ENERGY.to_string(), Object::LongArray(energy)),
+        ]));
+        receiver
+            .binder()
+            .r#send(self.result_code, &self.omit(bundle))
+    }
+}
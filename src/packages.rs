@@ -0,0 +1,154 @@
+//! Resolving the UIDs in [`crate::EnergyConsumerAttribution`] to package names
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use anyhow::Result;
+
+use crate::PowerStats;
+
+/// UIDs of apps are offset by this for every additional Android user (profile)
+const PER_USER_RANGE: i32 = 100_000;
+
+/// Resolves a UID to a human-readable package name
+pub trait PackageResolver {
+    fn package_name(&self, uid: i32) -> Option<String>;
+}
+
+/// Package names parsed from `/data/system/packages.list`, which is only readable on rooted
+/// devices.
+///
+/// Packages that share a UID (through `android:sharedUserId`) resolve to the first one listed.
+#[derive(Clone, Debug, Default)]
+pub struct PackagesList(HashMap<i32, String>);
+
+impl PackagesList {
+    pub const PATH: &'static str = "/data/system/packages.list";
+
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(Self::PATH)
+    }
+
+    pub fn load_from(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses lines of `<package> <uid> <debuggable> <data dir> <seinfo> <gids>...`, skipping any
+    /// that don't match
+    pub fn parse(contents: &str) -> Self {
+        let mut packages = HashMap::new();
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+            if let (Some(name), Some(Ok(uid))) = (fields.next(), fields.next().map(str::parse)) {
+                packages.entry(uid).or_insert_with(|| name.to_string());
+            }
+        }
+        Self(packages)
+    }
+}
+
+impl PackageResolver for PackagesList {
+    fn package_name(&self, uid: i32) -> Option<String> {
+        // The list only contains the app id, which is shared across users
+        self.0.get(&(uid % PER_USER_RANGE)).cloned()
+    }
+}
+
+impl PowerStats {
+    /// Sums the energy in `uWs` (uJ) attributed to every UID across all energy consumers, and
+    /// names them by their package through [`PackagesList`].  UIDs that can't be resolved
+    /// (including when [`PackagesList::PATH`] isn't readable) are named `uid:<n>` instead.
+    ///
+    /// Sorted by energy, highest first.
+    pub fn energy_by_package(&self) -> Result<Vec<(String, i64)>> {
+        self.energy_by_package_with(&PackagesList::load().unwrap_or_default())
+    }
+
+    /// Like [`Self::energy_by_package()`], resolving UIDs through `resolver`
+    pub fn energy_by_package_with(
+        &self,
+        resolver: &impl PackageResolver,
+    ) -> Result<Vec<(String, i64)>> {
+        let mut totals = HashMap::<i32, i128>::new();
+        for (_, reading) in self.read_all_consumers_with_attribution()? {
+            for a in reading.attribution {
+                *totals.entry(a.uid).or_default() += i128::from(a.energy_uws);
+            }
+        }
+
+        let mut packages = totals
+            .into_iter()
+            .map(|(uid, e)| {
+                let name = resolver
+                    .package_name(uid)
+                    .unwrap_or_else(|| format!("uid:{uid}"));
+                (name, e.clamp(i64::MIN.into(), i64::MAX.into()) as i64)
+            })
+            .collect::<Vec<_>>();
+        packages.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(packages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{android_hardware_power_stats::EnergyConsumerType as HalType, mock::*};
+
+    #[test]
+    fn parse_packages_list() {
+        let list = PackagesList::parse(
+            "com.android.shell 2000 0 /data/user_de/0/com.android.shell platform:privapp 3003\n\
+             nl.traverseresearch.app 10123 1 /data/user/0/nl.traverseresearch.app default:targetSdkVersion=34 none\n\
+             garbage\n",
+        );
+        assert_eq!(
+            list.package_name(2000).as_deref(),
+            Some("com.android.shell")
+        );
+        assert_eq!(
+            list.package_name(10123).as_deref(),
+            Some("nl.traverseresearch.app")
+        );
+        // Same app in a work profile
+        assert_eq!(
+            list.package_name(1010123).as_deref(),
+            Some("nl.traverseresearch.app")
+        );
+        assert_eq!(list.package_name(10124), None);
+    }
+
+    #[test]
+    fn energy_by_package() {
+        struct Stub;
+        impl PackageResolver for Stub {
+            fn package_name(&self, uid: i32) -> Option<String> {
+                (uid == 10001).then(|| "com.example.game".to_string())
+            }
+        }
+
+        let (stats, _) = MockPowerStats {
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(0, 0, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(1, 0, HalType::OTHER, "GPU"),
+                ])
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 5000, 1000, &[(10001, 400), (10002, 300)]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        assert_eq!(
+            stats.energy_by_package_with(&Stub).unwrap(),
+            [
+                ("com.example.game".to_string(), 800),
+                ("uid:10002".to_string(), 600)
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use log::warn;
+
+use crate::{EnergyMeterReading, PowerStats};
+
+/// Hides the latency of [`PowerStats::read_energy_meters()`] from i.e. a UI thread rendering at a
+/// fixed frame rate, by reading on a background thread ahead of time.  Every call to
+/// [`Self::latest()`] returns the most recent result instantly and requests the next one, so the
+/// readings are typically one call (frame) old.
+#[derive(Debug)]
+pub struct PrefetchReader {
+    latest: Arc<Mutex<Option<Vec<EnergyMeterReading>>>>,
+    /// Holds at most one pending request, so that slow reads don't pile up.  Dropping it stops the
+    /// background thread.
+    request: SyncSender<()>,
+}
+
+impl PrefetchReader {
+    /// Spawns the background thread and immediately starts the first read
+    pub fn new(stats: Arc<PowerStats>, meter_ids: &[i32]) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let (request, requests) = sync_channel(1);
+        let meter_ids = meter_ids.to_vec();
+        thread::spawn({
+            let latest = Arc::clone(&latest);
+            move || {
+                for () in requests {
+                    match stats.read_energy_meters(&meter_ids) {
+                        Ok(readings) => *latest.lock().unwrap() = Some(readings),
+                        Err(e) => warn!("Prefetching {meter_ids:?} failed with `{e:?}`"),
+                    }
+                }
+            }
+        });
+        request.send(()).unwrap();
+        Self { latest, request }
+    }
+
+    /// Most recent readings without blocking on the service, [`None`] until the first read
+    /// completes.  Failed reads are logged and leave the previous result in place.
+    pub fn latest(&self) -> Option<Vec<EnergyMeterReading>> {
+        let latest = self.latest.lock().unwrap().clone();
+        // Full if the previous request wasn't picked up yet, which is just as good
+        let _ = self.request.try_send(());
+        latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicI64, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn latest_doesnt_block() {
+        // Only the first read is fast
+        let reads = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let n = reads.fetch_add(1, Ordering::Relaxed);
+                if n > 0 {
+                    thread::sleep(Duration::from_secs(1));
+                }
+                Ok(ids.iter().map(|&id| measurement(id, 1000, 0, n)).collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let reader = PrefetchReader::new(Arc::new(stats), &[2]);
+        let first = Instant::now();
+        while reader.latest().is_none() {
+            assert!(first.elapsed() < Duration::from_secs(5), "Never prefetched");
+            thread::yield_now();
+        }
+
+        // The next read is in flight now, which must not hold up the caller
+        let start = Instant::now();
+        let latest = reader.latest().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(latest[0].energy_uws, 0);
+    }
+}
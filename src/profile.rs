@@ -0,0 +1,161 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{DeviceTopology, EnergyConsumer, EnergyMeter, PowerStats, PowerStatsError};
+
+/// A [`DeviceTopology`] tagged with the version of this crate that produced it, so that it can be
+/// stored and loaded again safely.  Deserializing fails with
+/// [`PowerStatsError::IncompatibleProfileVersion`] if the profile was written by a
+/// semver-incompatible version of this crate.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedDeviceProfile")]
+pub struct DeviceProfile {
+    crate_version: String,
+    /// Stored alongside [`Self::crate_version()`] rather than nested, as profiles were before
+    /// they wrapped a [`DeviceTopology`]
+    #[serde(flatten)]
+    pub topology: DeviceTopology,
+}
+
+#[derive(Deserialize)]
+struct UncheckedDeviceProfile {
+    crate_version: String,
+    #[serde(flatten)]
+    topology: DeviceTopology,
+}
+
+impl TryFrom<UncheckedDeviceProfile> for DeviceProfile {
+    type Error = PowerStatsError;
+
+    fn try_from(value: UncheckedDeviceProfile) -> Result<Self, Self::Error> {
+        let UncheckedDeviceProfile {
+            crate_version,
+            topology,
+        } = value;
+        if !is_compatible(&crate_version, env!("CARGO_PKG_VERSION")) {
+            return Err(PowerStatsError::IncompatibleProfileVersion {
+                found: crate_version,
+            });
+        }
+        Ok(Self {
+            crate_version,
+            topology,
+        })
+    }
+}
+
+/// Compatible according to Cargo's (caret) semver rules, where the first non-zero component is
+/// the one that must match
+fn is_compatible(a: &str, b: &str) -> bool {
+    let parse = |v: &str| -> Option<[u64; 3]> {
+        // Ignore pre-release and build metadata
+        let v = v.split(['-', '+']).next()?;
+        let mut parts = v.split('.').map(|p| p.parse().ok());
+        Some([parts.next()??, parts.next()??, parts.next()??])
+    };
+    let (Some(a), Some(b)) = (parse(a), parse(b)) else {
+        return false;
+    };
+    match a.iter().position(|&c| c != 0) {
+        Some(i) => a[..=i] == b[..=i],
+        None => a == b,
+    }
+}
+
+impl DeviceProfile {
+    pub fn new(meters: Vec<EnergyMeter>, consumers: Vec<EnergyConsumer>) -> Self {
+        DeviceTopology { meters, consumers }.into()
+    }
+
+    /// Version of this crate that created the profile
+    pub fn crate_version(&self) -> &str {
+        &self.crate_version
+    }
+}
+
+impl From<DeviceTopology> for DeviceProfile {
+    fn from(topology: DeviceTopology) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            topology,
+        }
+    }
+}
+
+impl PowerStats {
+    /// [`Self::topology()`] tagged with the version of this crate, to be stored
+    pub fn device_profile(&self) -> Result<DeviceProfile> {
+        Ok(self.topology()?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EnergyConsumerType;
+
+    #[test]
+    fn round_trip() {
+        let profile = DeviceProfile::new(
+            vec![EnergyMeter::new(
+                0,
+                "S9S_VDD_G3D".to_string(),
+                "GPU".to_string(),
+            )],
+            vec![EnergyConsumer {
+                id: 1,
+                name: "CPU".to_string(),
+                ordinal: 2,
+                r#type: EnergyConsumerType::CpuCluster,
+                raw_type: 2,
+            }],
+        );
+        let json = serde_json::to_string(&profile).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DeviceProfile>(&json).unwrap(),
+            profile
+        );
+    }
+
+    #[test]
+    fn missing_raw_type() {
+        let mut json = serde_json::to_value(DeviceProfile::new(
+            vec![],
+            vec![EnergyConsumer {
+                id: 1,
+                name: "CPU".to_string(),
+                ordinal: 2,
+                r#type: EnergyConsumerType::CpuCluster,
+                raw_type: 2,
+            }],
+        ))
+        .unwrap();
+        json["consumers"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("raw_type")
+            .unwrap();
+        let profile = serde_json::from_value::<DeviceProfile>(json).unwrap();
+        assert_eq!(profile.topology.consumers[0].raw_type, 2);
+    }
+
+    #[test]
+    fn incompatible_version() {
+        let mut json = serde_json::to_value(DeviceProfile::new(vec![], vec![])).unwrap();
+        json["crate_version"] = "1234.0.0".into();
+        let err = serde_json::from_value::<DeviceProfile>(json).unwrap_err();
+        assert!(err.to_string().contains("1234.0.0"), "{err}");
+    }
+
+    #[test]
+    fn compatibility() {
+        assert!(is_compatible("1.2.3", "1.4.0"));
+        assert!(!is_compatible("1.2.3", "2.0.0"));
+        assert!(is_compatible("0.3.1", "0.3.0"));
+        assert!(!is_compatible("0.3.1", "0.4.0"));
+        assert!(is_compatible("0.0.1", "0.0.1"));
+        assert!(!is_compatible("0.0.1", "0.0.2"));
+        assert!(is_compatible("0.2.0-rc.1", "0.2.0"));
+        assert!(!is_compatible("garbage", "0.2.0"));
+    }
+}
@@ -0,0 +1,445 @@
+//! Recording of every HAL call made through a [`PowerStats`] to a file, and replaying that file
+//! without the HAL.  Both are local [`IPowerStats`] services in front of
+//! [`crate::BackendSelection::VendorHardwareService`], so that replayed results go through exactly
+//! the same parsing as live ones.  Replaying still hosts such a service, so it needs
+//! `libbinder_ndk` like everything else in this crate: another Android device or an emulator
+//! rather than a development host.
+//!
+//! The log starts with [`MAGIC`], followed by one entry per call: its length in bytes as a
+//! little-endian `u32`, the method name, the requested ids and either the result or the
+//! (exception and status) codes that the call failed with.
+
+use std::{collections::VecDeque, fs::File, io::Write, path::Path, sync::Mutex};
+
+use anyhow::{bail, Context, Result};
+use binder::{BinderFeatures, ExceptionCode, Status, StatusCode, Strong};
+use log::warn;
+
+use crate::{
+    android_hardware_power_stats::{
+        BnPowerStats, Channel, EnergyConsumer, EnergyConsumerAttribution, EnergyConsumerResult,
+        EnergyConsumerType, EnergyMeasurement, IPowerStats, PowerEntity, State, StateResidency,
+        StateResidencyResult,
+    },
+    Backend, PowerStats,
+};
+
+/// Leads every log, with the version of the format in the last byte
+const MAGIC: [u8; 8] = *b"PWRSTLG\x01";
+
+/// Fixed binary encoding of the values that [`IPowerStats`] exchanges
+trait Record: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(input: &mut &[u8]) -> Option<Self>;
+}
+
+fn take<const N: usize>(input: &mut &[u8]) -> Option<[u8; N]> {
+    let bytes = input.get(..N)?.try_into().ok()?;
+    *input = &input[N..];
+    Some(bytes)
+}
+
+macro_rules! record_int {
+    ($($ty:ty),*) => {$(
+        impl Record for $ty {
+            fn write(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read(input: &mut &[u8]) -> Option<Self> {
+                take(input).map(Self::from_le_bytes)
+            }
+        }
+    )*};
+}
+
+record_int!(u8, i8, i32, u32, i64);
+
+impl<T: Record> Record for Vec<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).write(out);
+        for v in self {
+            v.write(out);
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Option<Self> {
+        let len = u32::read(input)?;
+        // Every element takes at least a byte, don't trust the length beyond that
+        let mut v = Vec::with_capacity((len as usize).min(input.len()));
+        for _ in 0..len {
+            v.push(T::read(input)?);
+        }
+        Some(v)
+    }
+}
+
+impl Record for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.as_bytes().to_vec().write(out);
+    }
+
+    fn read(input: &mut &[u8]) -> Option<Self> {
+        String::from_utf8(Vec::read(input)?).ok()
+    }
+}
+
+impl Record for EnergyConsumerType {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.0.write(out);
+    }
+
+    fn read(input: &mut &[u8]) -> Option<Self> {
+        i8::read(input).map(Self)
+    }
+}
+
+macro_rules! record_struct {
+    ($($ty:ident { $($field:ident),* })*) => {$(
+        impl Record for $ty {
+            fn write(&self, out: &mut Vec<u8>) {
+                $(self.$field.write(out);)*
+            }
+
+            fn read(input: &mut &[u8]) -> Option<Self> {
+                Some(Self { $($field: Record::read(input)?),* })
+            }
+        }
+    )*};
+}
+
+record_struct! {
+    Channel { id, name, subsystem }
+    EnergyMeasurement { id, timestampMs, durationMs, energyUWs }
+    EnergyConsumer { id, ordinal, r#type, name }
+    EnergyConsumerAttribution { uid, energyUWs }
+    EnergyConsumerResult { id, timestampMs, energyUWs, attribution }
+    State { id, name }
+    PowerEntity { id, name, states }
+    StateResidency { id, totalTimeInStateMs, totalStateEntryCount, lastEntryTimestampMs }
+    StateResidencyResult { id, stateResidencyData }
+}
+
+/// Failures are recorded by their codes, the message of a [`Status`] is lost
+fn status_codes(status: &Status) -> (i32, i32) {
+    (
+        status.exception_code() as i32,
+        status.transaction_error() as i32,
+    )
+}
+
+fn status_from_codes(exception: i32, transaction_error: i32) -> Status {
+    use ExceptionCode as E;
+    [
+        E::SECURITY,
+        E::BAD_PARCELABLE,
+        E::ILLEGAL_ARGUMENT,
+        E::NULL_POINTER,
+        E::ILLEGAL_STATE,
+        E::NETWORK_MAIN_THREAD,
+        E::UNSUPPORTED_OPERATION,
+        E::SERVICE_SPECIFIC,
+        E::PARCELABLE,
+    ]
+    .into_iter()
+    .find(|&e| e as i32 == exception)
+    .map_or_else(|| transaction_error.into(), Status::from)
+}
+
+/// Forwards every call to the real HAL and appends it to the log
+struct Recorder {
+    hal: Strong<dyn IPowerStats>,
+    log: Mutex<File>,
+}
+
+impl Recorder {
+    /// A failure to write the log doesn't fail the call, the session is worth more than its log
+    fn append<T: Record>(
+        &self,
+        method: &str,
+        ids: &[i32],
+        result: binder::Result<Vec<T>>,
+    ) -> binder::Result<Vec<T>> {
+        let mut entry = Vec::new();
+        method.to_string().write(&mut entry);
+        ids.to_vec().write(&mut entry);
+        match &result {
+            Ok(v) => {
+                0u8.write(&mut entry);
+                v.write(&mut entry);
+            }
+            Err(status) => {
+                let (exception, transaction_error) = status_codes(status);
+                1u8.write(&mut entry);
+                exception.write(&mut entry);
+                transaction_error.write(&mut entry);
+            }
+        }
+        let mut out = Vec::with_capacity(4 + entry.len());
+        (entry.len() as u32).write(&mut out);
+        out.extend_from_slice(&entry);
+        if let Err(e) = self.log.lock().unwrap().write_all(&out) {
+            warn!("Failed to record `{method}` because of `{e:?}`");
+        }
+        result
+    }
+}
+
+impl binder::Interface for Recorder {}
+
+#[allow(non_snake_case)]
+impl IPowerStats for Recorder {
+    fn getPowerEntityInfo(&self) -> binder::Result<Vec<PowerEntity>> {
+        self.append("getPowerEntityInfo", &[], self.hal.getPowerEntityInfo())
+    }
+
+    fn getStateResidency(&self, ids: &[i32]) -> binder::Result<Vec<StateResidencyResult>> {
+        self.append("getStateResidency", ids, self.hal.getStateResidency(ids))
+    }
+
+    fn getEnergyConsumerInfo(&self) -> binder::Result<Vec<EnergyConsumer>> {
+        self.append(
+            "getEnergyConsumerInfo",
+            &[],
+            self.hal.getEnergyConsumerInfo(),
+        )
+    }
+
+    fn getEnergyConsumed(&self, ids: &[i32]) -> binder::Result<Vec<EnergyConsumerResult>> {
+        self.append("getEnergyConsumed", ids, self.hal.getEnergyConsumed(ids))
+    }
+
+    fn getEnergyMeterInfo(&self) -> binder::Result<Vec<Channel>> {
+        self.append("getEnergyMeterInfo", &[], self.hal.getEnergyMeterInfo())
+    }
+
+    fn readEnergyMeter(&self, ids: &[i32]) -> binder::Result<Vec<EnergyMeasurement>> {
+        self.append("readEnergyMeter", ids, self.hal.readEnergyMeter(ids))
+    }
+}
+
+struct Entry {
+    method: String,
+    ids: Vec<i32>,
+    /// Still encoded, its type depends on `method`
+    result: Result<Vec<u8>, (i32, i32)>,
+}
+
+impl Entry {
+    fn read(mut input: &[u8]) -> Option<Self> {
+        let input = &mut input;
+        let method = String::read(input)?;
+        let ids = Vec::read(input)?;
+        let result = match u8::read(input)? {
+            0 => Ok(input.to_vec()),
+            1 => Err((i32::read(input)?, i32::read(input)?)),
+            _ => return None,
+        };
+        Some(Self {
+            method,
+            ids,
+            result,
+        })
+    }
+}
+
+/// Answers every call with the next entry of the log, which must be for the same method and ids
+struct Replayer {
+    entries: Mutex<VecDeque<Entry>>,
+}
+
+impl Replayer {
+    fn next<T: Record>(&self, method: &str, ids: &[i32]) -> binder::Result<Vec<T>> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.front() else {
+            warn!("Replay log ended before `{method}` for {ids:?}");
+            return Err(StatusCode::NOT_ENOUGH_DATA.into());
+        };
+        if entry.method != method || entry.ids != ids {
+            warn!(
+                "Replay log has `{}` for {:?} next, not `{method}` for {ids:?}",
+                entry.method, entry.ids
+            );
+            return Err(StatusCode::BAD_VALUE.into());
+        }
+        let entry = entries.pop_front().unwrap();
+        match entry.result {
+            Ok(result) => {
+                let mut input = result.as_slice();
+                match Vec::read(&mut input) {
+                    Some(v) if input.is_empty() => Ok(v),
+                    _ => {
+                        warn!("Malformed `{method}` result in replay log");
+                        Err(StatusCode::BAD_VALUE.into())
+                    }
+                }
+            }
+            Err((exception, transaction_error)) => {
+                Err(status_from_codes(exception, transaction_error))
+            }
+        }
+    }
+}
+
+impl binder::Interface for Replayer {}
+
+#[allow(non_snake_case)]
+impl IPowerStats for Replayer {
+    fn getPowerEntityInfo(&self) -> binder::Result<Vec<PowerEntity>> {
+        self.next("getPowerEntityInfo", &[])
+    }
+
+    fn getStateResidency(&self, ids: &[i32]) -> binder::Result<Vec<StateResidencyResult>> {
+        self.next("getStateResidency", ids)
+    }
+
+    fn getEnergyConsumerInfo(&self) -> binder::Result<Vec<EnergyConsumer>> {
+        self.next("getEnergyConsumerInfo", &[])
+    }
+
+    fn getEnergyConsumed(&self, ids: &[i32]) -> binder::Result<Vec<EnergyConsumerResult>> {
+        self.next("getEnergyConsumed", ids)
+    }
+
+    fn getEnergyMeterInfo(&self) -> binder::Result<Vec<Channel>> {
+        self.next("getEnergyMeterInfo", &[])
+    }
+
+    fn readEnergyMeter(&self, ids: &[i32]) -> binder::Result<Vec<EnergyMeasurement>> {
+        self.next("readEnergyMeter", ids)
+    }
+}
+
+impl PowerStats {
+    /// Returns a [`PowerStats`] with the same options that makes all its calls through this one,
+    /// and appends each of them (successful or not) to a new log at `path` for
+    /// [`Self::replay_from()`].  Only [`crate::BackendSelection::VendorHardwareService`] can be
+    /// recorded.
+    pub fn record_to(&self, path: impl AsRef<Path>) -> Result<Self> {
+        let hal = self.vendor_hal("record_to")?.clone();
+        let path = path.as_ref();
+        let mut log =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        log.write_all(&MAGIC)?;
+        let recorder = Recorder {
+            hal,
+            log: Mutex::new(log),
+        };
+        let mut recording = self.clone();
+        recording.backend = Backend::VendorHardwareService(BnPowerStats::new_binder(
+            recorder,
+            BinderFeatures::default(),
+        ));
+        recording.local_hal = true;
+        Ok(recording)
+    }
+
+    /// Serves the calls recorded by [`Self::record_to()`] in order, i.e. to analyze a session on a
+    /// device or emulator without the same HAL.  The same calls have to be made in the same order
+    /// as during the recording: any other call, or one past the end of the log, fails.
+    ///
+    /// Neither the replay nor the recording can be [`Self::reconnect()`]ed.
+    pub fn replay_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let log =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let Some(mut input) = log.strip_prefix(&MAGIC) else {
+            bail!("{} is not a recorded power stats log", path.display());
+        };
+        let mut entries = VecDeque::new();
+        while !input.is_empty() {
+            let entry = u32::read(&mut input)
+                .filter(|&len| len as usize <= input.len())
+                .and_then(|len| {
+                    let (entry, rest) = input.split_at(len as usize);
+                    input = rest;
+                    Entry::read(entry)
+                })
+                .with_context(|| {
+                    format!("Malformed entry {} in {}", entries.len(), path.display())
+                })?;
+            entries.push_back(entry);
+        }
+        let replayer = Replayer {
+            entries: Mutex::new(entries),
+        };
+        let mut replay = Self::from_backend(Backend::VendorHardwareService(
+            BnPowerStats::new_binder(replayer, BinderFeatures::default()),
+        ));
+        replay.local_hal = true;
+        Ok(replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::*, PowerStatsError};
+
+    #[test]
+    fn record_and_replay() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                ])
+            }),
+            read_energy_meter: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000, 10, 100 * id as i64))
+                    .collect())
+            }),
+            energy_consumed: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000, 300, &[(10123, 300)]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let path = std::env::temp_dir().join(format!("powerstats-{}.log", std::process::id()));
+        let recording = stats.record_to(&path).unwrap();
+        let meters = recording.energy_meters().unwrap();
+        let readings = recording.read_energy_meters(&[1, 0]).unwrap();
+        let consumers = recording.read_energy_consumers(&[7]).unwrap();
+        let err = recording.energy_consumers().unwrap_err();
+        let unsupported = Some(&PowerStatsError::Unsupported {
+            method: "reconnect",
+        });
+        assert_eq!(
+            recording.clone().reconnect().unwrap_err().downcast_ref(),
+            unsupported
+        );
+        drop(recording);
+
+        let replay = PowerStats::replay_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            replay.clone().reconnect().unwrap_err().downcast_ref(),
+            unsupported
+        );
+        assert_eq!(replay.energy_meters().unwrap(), meters);
+        // Out of order
+        assert!(replay.read_energy_consumers(&[7]).is_err());
+        assert_eq!(replay.read_energy_meters(&[1, 0]).unwrap(), readings);
+        assert_eq!(replay.read_energy_consumers(&[7]).unwrap(), consumers);
+        let transaction_error = |e: anyhow::Error| e.downcast_ref().map(Status::transaction_error);
+        assert_eq!(
+            transaction_error(replay.energy_consumers().unwrap_err()),
+            Some(StatusCode::UNKNOWN_TRANSACTION)
+        );
+        assert_eq!(
+            transaction_error(err),
+            Some(StatusCode::UNKNOWN_TRANSACTION)
+        );
+        // Past the end
+        assert_eq!(
+            transaction_error(replay.read_energy_meters(&[1, 0]).unwrap_err()),
+            Some(StatusCode::NOT_ENOUGH_DATA)
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! Time spent by power entities (blocks of the SoC, radios, ...) in each of their low-power
+//! states.  Only provided by [`crate::BackendSelection::VendorHardwareService`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::warn;
+
+use crate::{android_hardware_power_stats, permission_denied, PowerStats, PowerStatsError};
+
+/// <https://cs.android.com/android/platform/superproject/main/+/main:hardware/interfaces/power/stats/aidl/android/hardware/power/stats/PowerEntity.aidl>
+#[doc(alias = "android.hardware.power.stats.PowerEntity")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PowerEntity {
+    pub id: i32,
+    /// I.e. `GPU` or `Modem`
+    pub name: String,
+    /// Every state that [`StateResidencyResult::state_residency`] of this entity may refer to
+    pub states: Vec<PowerEntityState>,
+}
+
+impl PowerEntity {
+    /// Name of the state with id `state_id`, i.e. to label a [`StateResidency`]
+    pub fn state_name(&self, state_id: i32) -> Option<&str> {
+        self.states
+            .iter()
+            .find(|s| s.id == state_id)
+            .map(|s| s.name.as_str())
+    }
+}
+
+impl From<android_hardware_power_stats::PowerEntity> for PowerEntity {
+    fn from(value: android_hardware_power_stats::PowerEntity) -> Self {
+        let android_hardware_power_stats::PowerEntity { id, name, states } = value;
+        Self {
+            id,
+            name,
+            states: states.into_iter().map(|s| s.into()).collect(),
+        }
+    }
+}
+
+/// <https://cs.android.com/android/platform/superproject/main/+/main:hardware/interfaces/power/stats/aidl/android/hardware/power/stats/State.aidl>
+#[doc(alias = "android.hardware.power.stats.State")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PowerEntityState {
+    /// Id of the state within its power entity
+    pub id: i32,
+    /// I.e. `on`, `off` or `sleep`, which aren't standardized
+    pub name: String,
+}
+
+impl From<android_hardware_power_stats::State> for PowerEntityState {
+    fn from(value: android_hardware_power_stats::State) -> Self {
+        let android_hardware_power_stats::State { id, name } = value;
+        Self { id, name }
+    }
+}
+
+/// <https://cs.android.com/android/platform/superproject/main/+/main:hardware/interfaces/power/stats/aidl/android/hardware/power/stats/StateResidency.aidl>
+#[doc(alias = "android.hardware.power.stats.StateResidency")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StateResidency {
+    /// Id of the state within its power entity
+    pub id: i32,
+    /// Total time since boot spent in this state
+    pub total_time_in_state_ms: i64,
+    /// Number of times this state was entered since boot
+    pub total_state_entry_count: i64,
+    /// Monotonic timestamp since boot of the last time this state was entered
+    pub last_entry_timestamp_ms: i64,
+}
+
+impl From<android_hardware_power_stats::StateResidency> for StateResidency {
+    fn from(value: android_hardware_power_stats::StateResidency) -> Self {
+        let android_hardware_power_stats::StateResidency {
+            id,
+            totalTimeInStateMs,
+            totalStateEntryCount,
+            lastEntryTimestampMs,
+        } = value;
+        Self {
+            id,
+            total_time_in_state_ms: totalTimeInStateMs,
+            total_state_entry_count: totalStateEntryCount,
+            last_entry_timestamp_ms: lastEntryTimestampMs,
+        }
+    }
+}
+
+/// <https://cs.android.com/android/platform/superproject/main/+/main:hardware/interfaces/power/stats/aidl/android/hardware/power/stats/StateResidencyResult.aidl>
+#[doc(alias = "android.hardware.power.stats.StateResidencyResult")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateResidencyResult {
+    /// Id of the power entity
+    pub id: i32,
+    pub state_residency: Vec<StateResidency>,
+}
+
+impl From<android_hardware_power_stats::StateResidencyResult> for StateResidencyResult {
+    fn from(value: android_hardware_power_stats::StateResidencyResult) -> Self {
+        let android_hardware_power_stats::StateResidencyResult {
+            id,
+            stateResidencyData,
+        } = value;
+        Self {
+            id,
+            state_residency: stateResidencyData.into_iter().map(|r| r.into()).collect(),
+        }
+    }
+}
+
+impl PowerStats {
+    /// Discovers all power entities along with their states.  Only available on
+    /// [`crate::BackendSelection::VendorHardwareService`], fails with
+    /// [`PowerStatsError::Unsupported`] otherwise.
+    pub fn power_entities(&self) -> Result<Vec<PowerEntity>> {
+        let entities = self
+            .vendor_hal("getPowerEntityInfo")?
+            .getPowerEntityInfo()
+            .map_err(permission_denied("getPowerEntityInfo"))?;
+        Ok(entities.into_iter().map(|e| e.into()).collect())
+    }
+
+    /// Reads the state residency of the power entities in `entity_ids`, see
+    /// [`Self::power_entities()`] for the names of their states.  Passing no ids at all reads every
+    /// entity on most HALs, [`Self::read_all_state_residency()`] does so reliably.  Only
+    /// available on [`crate::BackendSelection::VendorHardwareService`], fails with
+    /// [`PowerStatsError::Unsupported`] otherwise.
+    pub fn read_state_residency(&self, entity_ids: &[i32]) -> Result<Vec<StateResidencyResult>> {
+        let residency = self
+            .vendor_hal("getStateResidency")?
+            .getStateResidency(entity_ids)
+            .map_err(permission_denied("getStateResidency"))?;
+        Ok(residency.into_iter().map(|r| r.into()).collect())
+    }
+
+    /// Reads the state residency of all power entities.  Passing no ids to the HAL is supposed to
+    /// return all of them, but when a HAL rejects that (or returns nothing) the entities are
+    /// discovered and requested explicitly instead.
+    pub fn read_all_state_residency(&self) -> Result<Vec<StateResidencyResult>> {
+        let s = self.vendor_hal("getStateResidency")?;
+        let residency = match s.getStateResidency(&[]) {
+            Ok(residency) if !residency.is_empty() => residency,
+            result => {
+                if let Err(status) = result {
+                    let e = permission_denied("getStateResidency")(status);
+                    if e.is::<PowerStatsError>() {
+                        return Err(e);
+                    }
+                    warn!("`getStateResidency([])` failed with `{e:?}`, requesting all entities explicitly");
+                }
+                let ids = s
+                    .getPowerEntityInfo()
+                    .map_err(permission_denied("getPowerEntityInfo"))?
+                    .iter()
+                    .map(|e| e.id)
+                    .collect::<Vec<_>>();
+                if ids.is_empty() {
+                    return Ok(vec![]);
+                }
+                s.getStateResidency(&ids)
+                    .map_err(permission_denied("getStateResidency"))?
+            }
+        };
+        Ok(residency.into_iter().map(|r| r.into()).collect())
+    }
+
+    fn entities_with_residency(
+        &self,
+    ) -> Result<(
+        Vec<android_hardware_power_stats::PowerEntity>,
+        Vec<android_hardware_power_stats::StateResidencyResult>,
+    )> {
+        let s = self.vendor_hal("getPowerEntityInfo")?;
+        let entities = s
+            .getPowerEntityInfo()
+            .map_err(permission_denied("getPowerEntityInfo"))?;
+        let ids = entities.iter().map(|e| e.id).collect::<Vec<_>>();
+        let residency = s
+            .getStateResidency(&ids)
+            .map_err(permission_denied("getStateResidency"))?;
+        Ok((entities, residency))
+    }
+
+    /// Sums the time spent in every state across all power entities that have a state with that
+    /// name, i.e. the total time any block spent `"off"`.  Entities don't need to share the same
+    /// set of states.  Blocks are in their states at the same time, so these sums can exceed the
+    /// wall-clock time (and saturate rather than overflow).
+    pub fn aggregate_residency_by_state_name(&self) -> Result<HashMap<String, Duration>> {
+        let (entities, residency) = self.entities_with_residency()?;
+        let state_names = entities
+            .iter()
+            .flat_map(|e| e.states.iter().map(|s| ((e.id, s.id), s.name.as_str())))
+            .collect::<HashMap<_, _>>();
+
+        let mut totals = HashMap::<String, Duration>::new();
+        for result in &residency {
+            for r in &result.stateResidencyData {
+                let Some(&name) = state_names.get(&(result.id, r.id)) else {
+                    warn!(
+                        "Residency reported for unknown state {} of entity {}",
+                        r.id, result.id
+                    );
+                    continue;
+                };
+                let time = Duration::from_millis(r.totalTimeInStateMs.try_into().unwrap_or(0));
+                let total = totals.entry(name.to_string()).or_default();
+                *total = total.saturating_add(time);
+            }
+        }
+        Ok(totals)
+    }
+
+    /// Longest time that any single power entity spent in its states whose name contains `sleep`
+    /// or `suspend` (case-insensitive).  The states of one entity don't overlap, but blocks sleep
+    /// at the same time, so this takes the maximum across entities rather than their sum, which
+    /// could exceed the wall-clock time.  State names aren't standardized, so this is only a
+    /// best-effort (lower bound of the) device-wide suspend time.
+    pub fn total_suspend_time(&self) -> Result<Duration> {
+        let (entities, residency) = self.entities_with_residency()?;
+        let suspend_states = entities
+            .iter()
+            .flat_map(|e| {
+                e.states
+                    .iter()
+                    .filter(|s| {
+                        let name = s.name.to_ascii_lowercase();
+                        name.contains("sleep") || name.contains("suspend")
+                    })
+                    .map(|s| (e.id, s.id))
+            })
+            .collect::<HashSet<_>>();
+
+        Ok(residency
+            .iter()
+            .map(|result| {
+                result
+                    .stateResidencyData
+                    .iter()
+                    .filter(|r| suspend_states.contains(&(result.id, r.id)))
+                    .map(|r| Duration::from_millis(r.totalTimeInStateMs.try_into().unwrap_or(0)))
+                    .fold(Duration::ZERO, Duration::saturating_add)
+            })
+            .max()
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binder::ExceptionCode;
+
+    use super::*;
+    use crate::mock::*;
+
+    fn residency(
+        id: i32,
+        states: &[(i32, i64)],
+    ) -> android_hardware_power_stats::StateResidencyResult {
+        android_hardware_power_stats::StateResidencyResult {
+            id,
+            stateResidencyData: states
+                .iter()
+                .map(|&(id, ms)| android_hardware_power_stats::StateResidency {
+                    id,
+                    totalTimeInStateMs: ms,
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn aggregate_residency_by_state_name() {
+        let (stats, _) = MockPowerStats {
+            power_entity_info: Box::new(|| {
+                Ok(vec![
+                    entity(0, "GPU", &["on", "off"]),
+                    entity(1, "Modem", &["on", "sleep", "off"]),
+                ])
+            }),
+            state_residency: Box::new(|ids| {
+                assert_eq!(ids, [0, 1]);
+                Ok(vec![
+                    residency(0, &[(0, 100), (1, 2000)]),
+                    // Unknown state 7 is skipped
+                    residency(1, &[(0, 50), (1, 300), (2, 4000), (7, 1)]),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        assert_eq!(
+            stats.aggregate_residency_by_state_name().unwrap(),
+            HashMap::from([
+                ("on".to_string(), Duration::from_millis(150)),
+                ("off".to_string(), Duration::from_millis(6000)),
+                ("sleep".to_string(), Duration::from_millis(300)),
+            ])
+        );
+        assert_eq!(
+            stats.total_suspend_time().unwrap(),
+            Duration::from_millis(300)
+        );
+    }
+
+    #[test]
+    fn total_suspend_time() {
+        let (stats, _) = MockPowerStats {
+            power_entity_info: Box::new(|| {
+                Ok(vec![
+                    entity(0, "SoC", &["active", "sleep", "deep_suspend"]),
+                    entity(1, "Modem", &["on", "SLEEP"]),
+                    entity(2, "GPU", &["on", "sleep"]),
+                ])
+            }),
+            state_residency: Box::new(|_| {
+                Ok(vec![
+                    residency(0, &[(0, 100), (1, 300), (2, 600)]),
+                    residency(1, &[(0, 50), (1, 800)]),
+                    residency(2, &[(0, 10), (1, 700)]),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        // Both suspend states of the SoC, rather than the sum of all entities
+        assert_eq!(
+            stats.total_suspend_time().unwrap(),
+            Duration::from_millis(900)
+        );
+    }
+
+    #[test]
+    fn power_entities() {
+        let (stats, calls) = MockPowerStats {
+            power_entity_info: Box::new(|| {
+                Ok(vec![
+                    entity(0, "GPU", &["on", "off"]),
+                    entity(1, "Modem", &["on", "sleep"]),
+                ])
+            }),
+            state_residency: Box::new(|ids| {
+                Ok(ids
+                    .iter()
+                    .map(|&id| residency(id, &[(0, 100), (1, 2000)]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let entities = stats.power_entities().unwrap();
+        assert_eq!(
+            entities[1],
+            PowerEntity {
+                id: 1,
+                name: "Modem".to_string(),
+                states: vec![
+                    PowerEntityState {
+                        id: 0,
+                        name: "on".to_string()
+                    },
+                    PowerEntityState {
+                        id: 1,
+                        name: "sleep".to_string()
+                    },
+                ],
+            }
+        );
+
+        let residency = stats.read_state_residency(&[1]).unwrap();
+        assert_eq!(residency.len(), 1);
+        let sleep = residency[0].state_residency[1];
+        assert_eq!(entities[1].state_name(sleep.id), Some("sleep"));
+        assert_eq!(sleep.total_time_in_state_ms, 2000);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("getPowerEntityInfo", vec![]),
+                ("getStateResidency", vec![1])
+            ]
+        );
+    }
+
+    #[test]
+    fn power_entities_unsupported() {
+        let (stats, _) = MockPowerStatsService::default().into_power_stats();
+        assert!(matches!(
+            stats.power_entities().unwrap_err().downcast_ref(),
+            Some(PowerStatsError::Unsupported {
+                method: "getPowerEntityInfo"
+            })
+        ));
+        assert!(matches!(
+            stats.read_state_residency(&[0]).unwrap_err().downcast_ref(),
+            Some(PowerStatsError::Unsupported {
+                method: "getStateResidency"
+            })
+        ));
+    }
+
+    #[test]
+    fn read_all_state_residency() {
+        let (stats, calls) = MockPowerStats {
+            state_residency: Box::new(|ids| {
+                assert!(ids.is_empty());
+                Ok(vec![residency(0, &[(0, 100)]), residency(1, &[(0, 200)])])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let all = stats.read_all_state_residency().unwrap();
+        assert_eq!(all.iter().map(|r| r.id).collect::<Vec<_>>(), [0, 1],);
+        assert_eq!(all[1].state_residency[0].total_time_in_state_ms, 200);
+        assert_eq!(*calls.lock().unwrap(), [("getStateResidency", vec![])]);
+    }
+
+    #[test]
+    fn read_all_state_residency_fallback() {
+        let (stats, calls) = MockPowerStats {
+            power_entity_info: Box::new(|| Ok(vec![entity(3, "GPU", &["on", "off"])])),
+            state_residency: Box::new(|ids| match ids {
+                [] => Err(ExceptionCode::ILLEGAL_ARGUMENT.into()),
+                ids => Ok(ids.iter().map(|&id| residency(id, &[(1, 42)])).collect()),
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let all = stats.read_all_state_residency().unwrap();
+        assert_eq!(
+            all,
+            [StateResidencyResult {
+                id: 3,
+                state_residency: vec![StateResidency {
+                    id: 1,
+                    total_time_in_state_ms: 42,
+                    total_state_entry_count: 0,
+                    last_entry_timestamp_ms: 0,
+                }],
+            }]
+        );
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("getStateResidency", vec![]),
+                ("getPowerEntityInfo", vec![]),
+                ("getStateResidency", vec![3]),
+            ]
+        );
+    }
+}
@@ -38,6 +38,11 @@ impl ResultReceiver {
         );
         Self { binder }
     }
+
+    #[cfg(any(test, feature = "bench"))]
+    pub(crate) fn binder(&self) -> &Strong<dyn IResultReceiver> {
+        &self.binder
+    }
 }
 
 impl Serialize for ResultReceiver {
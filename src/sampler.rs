@@ -0,0 +1,438 @@
+use std::{
+    collections::VecDeque,
+    ops::Deref,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{EnergyDelta, EnergyMeterReading, PowerStats};
+
+/// Source of the timestamps that [`PowerSampler`] computes elapsed time (and thus power) from
+pub trait Clock {
+    /// Monotonic timestamp to attribute to `reading`
+    fn timestamp(&mut self, reading: &EnergyMeterReading) -> Duration;
+}
+
+/// Uses [`EnergyMeterReading::timestamp`] as reported by the service
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReadingClock;
+
+impl Clock for ReadingClock {
+    fn timestamp(&mut self, reading: &EnergyMeterReading) -> Duration {
+        reading.timestamp
+    }
+}
+
+impl<F: FnMut(&EnergyMeterReading) -> Duration> Clock for F {
+    fn timestamp(&mut self, reading: &EnergyMeterReading) -> Duration {
+        self(reading)
+    }
+}
+
+/// Energy and average power of a single meter since the previous [`PowerSampler::sample()`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PowerSample {
+    pub id: i32,
+    /// Energy in `uWs` (uJ) accumulated since the previous sample
+    pub energy_uws: i64,
+    pub elapsed: Duration,
+    /// Average power over [`Self::elapsed`], [`None`] for the first sample, for
+    /// [`PowerSampler::stale_rails()`], for [`PowerSampler::reset_rails()`] and for samples that
+    /// [`PowerSampler::spanned_suspend()`]
+    pub watts: Option<f64>,
+    /// [`Self::watts`] after [`MeterSmoothing`], the same as [`Self::watts`] without it.  A moving
+    /// average is only [`None`] until the first power is known, and holds its value while the rail
+    /// is stale.
+    pub smoothed_watts: Option<f64>,
+}
+
+/// Smoothing that [`PowerSampler`] applies to [`PowerSample::smoothed_watts`], for rails that are
+/// too noisy to look at sample by sample
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeterSmoothing {
+    #[default]
+    None,
+    /// Average of the power of the last `window` samples (that had any) of every rail.  Note that
+    /// this lags behind actual changes in power by up to `window` samples.
+    MovingAverage { window: usize },
+}
+
+/// Repeatedly reads a fixed set of energy meters and turns their accumulated energy into power.
+/// Elapsed time comes from the monotonic [`EnergyMeterReading::timestamp`]s rather than
+/// [`EnergyMeterReading::duration`], which the Java service never reports and some HALs don't
+/// advance (see [`PowerStats::duration_is_reliable()`]).  Holds on to the [`PowerStats`] through
+/// any reference or smart pointer `S`, i.e. an [`Arc`] to move the sampler to another thread.
+///
+/// [`Arc`]: std::sync::Arc
+#[derive(Debug)]
+pub struct PowerSampler<S: Deref<Target = PowerStats>, C: Clock = ReadingClock> {
+    stats: S,
+    ids: Vec<i32>,
+    clock: C,
+    previous: Option<Vec<(Duration, i64)>>,
+    stale: Vec<i32>,
+    reset: Vec<i32>,
+    smoothing: MeterSmoothing,
+    /// Recent power of every rail for [`MeterSmoothing::MovingAverage`], in the order of `ids`
+    history: Vec<VecDeque<f64>>,
+    /// See [`Self::with_suspend_detection()`]
+    suspend_threshold: Option<Duration>,
+    /// When the previous [`Self::sample()`] returned from the service
+    previous_read_at: Option<Instant>,
+    spanned_suspend: bool,
+}
+
+impl<S: Deref<Target = PowerStats>> PowerSampler<S> {
+    pub fn new(stats: S, meter_ids: &[i32]) -> Self {
+        Self::with_clock(stats, meter_ids, ReadingClock)
+    }
+}
+
+impl<S: Deref<Target = PowerStats>, C: Clock> PowerSampler<S, C> {
+    /// Takes timestamps from `clock` instead of the readings, i.e. to drive the sampler with a
+    /// fake clock in tests
+    pub fn with_clock(stats: S, meter_ids: &[i32], clock: C) -> Self {
+        Self {
+            stats,
+            ids: meter_ids.to_vec(),
+            clock,
+            previous: None,
+            stale: Vec::new(),
+            reset: Vec::new(),
+            smoothing: MeterSmoothing::None,
+            history: vec![VecDeque::new(); meter_ids.len()],
+            suspend_threshold: None,
+            previous_read_at: None,
+            spanned_suspend: false,
+        }
+    }
+
+    pub fn with_smoothing(mut self, smoothing: MeterSmoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Detects samples that span a suspend of the device, for which energy is still reported but
+    /// [`PowerSample::watts`] is [`None`] (and doesn't take part in [`MeterSmoothing`]).  Rails
+    /// usually stop accumulating (or accumulate very little) while suspended, so the average power
+    /// over such an interval says nothing about the power while the device was awake.
+    ///
+    /// Reading timestamps are in `CLOCK_BOOTTIME`, which keeps counting during suspend, whereas
+    /// [`Instant`] (`CLOCK_MONOTONIC`) doesn't.  A sample is considered to span a suspend when its
+    /// [`PowerSample::elapsed`] exceeds the [`Instant`] time between both reads by more than
+    /// `threshold`, which must leave room for the latency of the service and for a [`Clock`] that
+    /// doesn't match either exactly.
+    pub fn with_suspend_detection(mut self, threshold: Duration) -> Self {
+        self.suspend_threshold = Some(threshold);
+        self
+    }
+
+    /// Reads all meters and returns a sample per meter in the order they were passed in.  The first
+    /// call only establishes a baseline, and returns zero energy without power.
+    pub fn sample(&mut self) -> Result<Vec<PowerSample>> {
+        let readings = self.stats.read_energy_meters(&self.ids)?;
+        let read_at = Instant::now();
+        let current = readings
+            .iter()
+            .map(|r| (self.clock.timestamp(r), r.energy_uws))
+            .collect::<Vec<_>>();
+
+        let mut stale = Vec::new();
+        let mut reset = Vec::new();
+        let mut samples: Vec<_> = match &self.previous {
+            Some(previous) => self
+                .ids
+                .iter()
+                .zip(previous.iter().zip(&current))
+                .map(|(&id, (&(start, earlier), &(end, later)))| {
+                    if end <= start {
+                        stale.push(id);
+                    }
+                    let delta = EnergyDelta {
+                        start,
+                        end,
+                        energy_uws: later.wrapping_sub(earlier),
+                    };
+                    if delta.energy_uws < 0 {
+                        // Unknown how much accumulated before and after the reset, the new value
+                        // is the baseline of the next sample
+                        reset.push(id);
+                        return PowerSample {
+                            id,
+                            energy_uws: 0,
+                            elapsed: delta.elapsed().unwrap_or_default(),
+                            watts: None,
+                            smoothed_watts: None,
+                        };
+                    }
+                    PowerSample {
+                        id,
+                        energy_uws: delta.energy_uws,
+                        elapsed: delta.elapsed().unwrap_or_default(),
+                        watts: delta.watts(),
+                        smoothed_watts: None,
+                    }
+                })
+                .collect(),
+            None => self
+                .ids
+                .iter()
+                .map(|&id| PowerSample {
+                    id,
+                    energy_uws: 0,
+                    elapsed: Duration::ZERO,
+                    watts: None,
+                    smoothed_watts: None,
+                })
+                .collect(),
+        };
+
+        let spanned_suspend = match (self.suspend_threshold, self.previous_read_at) {
+            (Some(threshold), Some(previous_read_at)) => {
+                let awake = read_at.saturating_duration_since(previous_read_at);
+                samples.iter().any(|s| s.elapsed > awake + threshold)
+            }
+            _ => false,
+        };
+        if spanned_suspend {
+            for sample in &mut samples {
+                sample.watts = None;
+            }
+        }
+
+        for (sample, history) in samples.iter_mut().zip(&mut self.history) {
+            sample.smoothed_watts = match self.smoothing {
+                MeterSmoothing::None => sample.watts,
+                MeterSmoothing::MovingAverage { window } => {
+                    if let Some(watts) = sample.watts {
+                        history.push_back(watts);
+                        while history.len() > window.max(1) {
+                            history.pop_front();
+                        }
+                    }
+                    (!history.is_empty())
+                        .then(|| history.iter().sum::<f64>() / history.len() as f64)
+                }
+            };
+        }
+
+        self.previous = Some(current);
+        self.stale = stale;
+        self.reset = reset;
+        self.previous_read_at = Some(read_at);
+        self.spanned_suspend = spanned_suspend;
+        Ok(samples)
+    }
+
+    /// Whether the last [`Self::sample()`] spanned a suspend, see
+    /// [`Self::with_suspend_detection()`]
+    pub fn spanned_suspend(&self) -> bool {
+        self.spanned_suspend
+    }
+
+    /// Meters whose timestamp didn't advance during the last [`Self::sample()`].  Some rails
+    /// only update on certain events, power can't be computed for them until they do.
+    pub fn stale_rails(&self) -> &[i32] {
+        &self.stale
+    }
+
+    /// Meters whose accumulated energy went backwards during the last [`Self::sample()`], because
+    /// the counter wrapped or was reset (i.e. by a restart of the service).  Their sample has zero
+    /// energy and no power, and the new reading is the baseline for the next one.
+    pub fn reset_rails(&self) -> &[i32] {
+        &self.reset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        sync::atomic::{AtomicI64, Ordering},
+    };
+
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn fake_clock() {
+        // Accumulates 50mJ on every read, while the readings claim no time passes at all
+        let energy = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let e = energy.fetch_add(50_000, Ordering::Relaxed);
+                Ok(ids.iter().map(|&id| measurement(id, 0, 0, e)).collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut now = Duration::ZERO;
+        let mut sampler = PowerSampler::with_clock(&stats, &[3], |_: &EnergyMeterReading| {
+            now += Duration::from_millis(100);
+            now
+        });
+
+        let baseline = sampler.sample().unwrap();
+        assert_eq!(
+            baseline,
+            [PowerSample {
+                id: 3,
+                energy_uws: 0,
+                elapsed: Duration::ZERO,
+                watts: None,
+                smoothed_watts: None
+            }]
+        );
+        for _ in 0..3 {
+            let sample = sampler.sample().unwrap();
+            assert_eq!(
+                sample,
+                [PowerSample {
+                    id: 3,
+                    energy_uws: 50_000,
+                    elapsed: Duration::from_millis(100),
+                    watts: Some(0.5),
+                    smoothed_watts: Some(0.5)
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn stale_rails() {
+        // Meter 1 never updates its timestamp
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                Ok(ids
+                    .iter()
+                    .map(|&id| match id {
+                        1 => measurement(id, 1000, 1000, 5000),
+                        _ => measurement(id, 1000 + 100 * t, 1000, 5000 + 1000 * t),
+                    })
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut sampler = PowerSampler::new(&stats, &[0, 1]);
+        sampler.sample().unwrap();
+        assert!(sampler.stale_rails().is_empty());
+
+        let samples = sampler.sample().unwrap();
+        assert_eq!(sampler.stale_rails(), [1]);
+        assert!(samples[0].watts.is_some());
+        assert_eq!(samples[1].watts, None);
+    }
+
+    #[test]
+    fn moving_average() {
+        // Alternates between 0.5W and 1.5W around a mean of 1W, over 100ms samples
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                let energy = (0..t).map(|i| [50_000, 150_000][i as usize % 2]).sum();
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000 + 100 * t, 0, energy))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut sampler = PowerSampler::new(&stats, &[0])
+            .with_smoothing(MeterSmoothing::MovingAverage { window: 4 });
+        sampler.sample().unwrap();
+        let samples = (0..8)
+            .map(|_| sampler.sample().unwrap()[0])
+            .collect::<Vec<_>>();
+
+        // Raw power is still available as is
+        assert!((samples[0].watts.unwrap() - 0.5).abs() < 1e-9);
+        assert!((samples[1].watts.unwrap() - 1.5).abs() < 1e-9);
+        for sample in &samples[3..] {
+            let smoothed = sample.smoothed_watts.unwrap();
+            assert!((smoothed - 1.0).abs() < 1e-9, "{smoothed}");
+        }
+    }
+
+    #[test]
+    fn suspend() {
+        // 0.5W over 100ms reads (in reading time)
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000 + 100 * t, 0, 50_000 * t))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        // Boot time jumps ahead by an hour while the test barely takes any time
+        let suspended = Cell::new(Duration::ZERO);
+        let mut sampler = PowerSampler::with_clock(&stats, &[0], |r: &EnergyMeterReading| {
+            r.timestamp + suspended.get()
+        })
+        .with_smoothing(MeterSmoothing::MovingAverage { window: 4 })
+        .with_suspend_detection(Duration::from_secs(60));
+        sampler.sample().unwrap();
+        let awake = sampler.sample().unwrap()[0];
+        assert!(!sampler.spanned_suspend());
+        assert!((awake.watts.unwrap() - 0.5).abs() < 1e-9);
+
+        suspended.set(Duration::from_secs(3600));
+        let across = sampler.sample().unwrap()[0];
+        assert!(sampler.spanned_suspend());
+        assert_eq!(across.energy_uws, 50_000);
+        assert_eq!(across.watts, None);
+        // Not averaged into the smoothed power either
+        assert!((across.smoothed_watts.unwrap() - 0.5).abs() < 1e-9);
+
+        sampler.sample().unwrap();
+        assert!(!sampler.spanned_suspend());
+    }
+
+    #[test]
+    fn counter_reset() {
+        // 0.5W over 100ms reads, but the counter restarts from zero on the fourth read
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                let energy = 50_000 * if t < 3 { 10 + t } else { t - 3 };
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000 + 100 * t, 0, energy))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut sampler = stats.sampler(&[0]);
+        sampler.sample().unwrap();
+        sampler.sample().unwrap();
+        sampler.sample().unwrap();
+        assert!(sampler.reset_rails().is_empty());
+
+        let across = sampler.sample().unwrap()[0];
+        assert_eq!(sampler.reset_rails(), [0]);
+        assert_eq!(across.energy_uws, 0);
+        assert_eq!(across.watts, None);
+
+        let after = sampler.sample().unwrap()[0];
+        assert!(sampler.reset_rails().is_empty());
+        assert_eq!(after.energy_uws, 50_000);
+        assert!((after.watts.unwrap() - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,223 @@
+use std::{panic, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use binder::Strong;
+use futures_util::stream::{self, Stream};
+use tokio::{
+    task,
+    time::{self, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    android_os_powerstatsservice::{IPowerStatsService, ReceivePowerMonitorReadings},
+    in_order_of, permission_denied, power_monitor_readings,
+    result_receiver::ResultReceiver,
+    unique_ids, Backend, EnergyConsumerReading, EnergyMeterReading, PowerSample, PowerSampler,
+    PowerStats,
+};
+
+/// Awaits a `getPowerMonitorReadings` round trip through a receiver of its own, so that concurrent
+/// reads don't need to wait for each other
+async fn read_power_monitors(
+    s: &Strong<dyn IPowerStatsService>,
+    ids: &[i32],
+) -> Result<Vec<EnergyMeterReading>> {
+    let (receiver, chan) = ReceivePowerMonitorReadings::new_oneshot();
+    let receiver = ResultReceiver::new(receiver);
+    s.getPowerMonitorReadings(ids, &receiver)
+        .map_err(permission_denied("getPowerMonitorReadings"))?;
+    // Disconnects the channel when the service drops the receiver without calling back
+    drop(receiver);
+    let readings = chan
+        .await
+        .context("ResultReceiver was dropped without delivering readings")??;
+    Ok(power_monitor_readings(readings, ids))
+}
+
+impl PowerStats {
+    /// Runs a blocking binder call `f` through [`task::spawn_blocking()`]
+    async fn blocking<T: Send + 'static>(
+        self: &Arc<Self>,
+        f: impl FnOnce(&Self) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let stats = Arc::clone(self);
+        task::spawn_blocking(move || f(&stats))
+            .await
+            .unwrap_or_else(|e| panic::resume_unwind(e.into_panic()))
+    }
+
+    /// Async counterpart of [`Self::read_energy_meters()`].  The Java service calls back with its
+    /// readings, which is awaited without occupying a thread.  The other backends only have
+    /// blocking calls, which are moved to [`task::spawn_blocking()`].
+    ///
+    /// Bound the wait with i.e. [`time::timeout()`], a result that arrives after this future was
+    /// dropped is discarded.
+    pub async fn read_energy_meters_async(
+        self: &Arc<Self>,
+        meter_ids: &[i32],
+    ) -> Result<Vec<EnergyMeterReading>> {
+        let Backend::SystemJavaService(s) = &self.backend else {
+            let meter_ids = meter_ids.to_vec();
+            return self
+                .blocking(move |stats| stats.read_energy_meters(&meter_ids))
+                .await;
+        };
+        let unique = unique_ids(meter_ids);
+        let readings = read_power_monitors(s, &unique).await?;
+        if unique.len() == meter_ids.len() {
+            return Ok(readings);
+        }
+        in_order_of(meter_ids, &unique.into_iter().zip(readings).collect())
+    }
+
+    /// Async counterpart of [`Self::read_energy_consumers()`], see
+    /// [`Self::read_energy_meters_async()`]
+    pub async fn read_energy_consumers_async(
+        self: &Arc<Self>,
+        consumer_ids: &[i32],
+    ) -> Result<Vec<EnergyConsumerReading>> {
+        let Backend::SystemJavaService(s) = &self.backend else {
+            let consumer_ids = consumer_ids.to_vec();
+            return self
+                .blocking(move |stats| stats.read_energy_consumers(&consumer_ids))
+                .await;
+        };
+        let monitors = read_power_monitors(s, consumer_ids).await?;
+        Ok(monitors.into_iter().map(|m| m.into()).collect())
+    }
+
+    /// Async counterpart of [`Self::refresh()`], after which discovery is served without waiting on
+    /// the Java service
+    pub async fn refresh_async(&self) -> Result<()> {
+        if let Backend::SystemJavaService(s) = &self.backend {
+            let monitors = s.receive_supported_power_monitors_async().await?;
+            *self.supported_power_monitors.write().unwrap() = Some(monitors);
+        }
+        Ok(())
+    }
+
+    /// Async counterpart of [`PowerSampler`], sampling `meter_ids` every `period`.  The first item
+    /// is yielded immediately and only establishes a baseline, i.e. has zero energy and no power.
+    ///
+    /// Binder calls are blocking, so every read is moved to [`task::spawn_blocking()`] to keep the
+    /// executor free.  Ticks missed because a read took longer than `period` are delayed rather
+    /// than bursted.
+    pub fn power_stream(
+        self: &Arc<Self>,
+        meter_ids: &[i32],
+        period: Duration,
+    ) -> impl Stream<Item = Result<Vec<PowerSample>>> {
+        let sampler = PowerSampler::new(Arc::clone(self), meter_ids);
+        // Can only be created once polled inside the runtime
+        let interval = None::<Interval>;
+        stream::unfold(
+            (sampler, interval),
+            move |(mut sampler, interval)| async move {
+                let mut interval = interval.unwrap_or_else(|| {
+                    let mut interval = time::interval(period);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    interval
+                });
+                interval.tick().await;
+                let (sampler, samples) = task::spawn_blocking(move || {
+                    let samples = sampler.sample();
+                    (sampler, samples)
+                })
+                .await
+                .unwrap_or_else(|e| panic::resume_unwind(e.into_panic()));
+                Some((samples, (sampler, Some(interval))))
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::{android_os_powerstatsservice::PowerMonitorType, mock::*};
+
+    #[tokio::test]
+    async fn power_stream() {
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            read_energy_meter: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                Ok(ids
+                    .iter()
+                    .map(|&id| measurement(id, 1000 + 100 * t, 0, 1000 * t))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let stats = Arc::new(stats);
+        let samples = stats
+            .power_stream(&[0], Duration::from_millis(10))
+            .take(2)
+            .collect::<Vec<_>>()
+            .await;
+        let samples = samples.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(samples[0][0].watts, None);
+        assert_eq!(samples[1][0].energy_uws, 1000);
+        assert_eq!(samples[1][0].watts, Some(0.01));
+    }
+
+    #[tokio::test]
+    async fn java_reads_async() {
+        // Never respond to reads of meter 0
+        let (stats, calls) = MockPowerStatsService {
+            supported_power_monitors: Box::new(|| {
+                vec![
+                    monitor(0, PowerMonitorType::Measurement, "[S9S_VDD_G3D]:GPU"),
+                    monitor(1, PowerMonitorType::Consumer, "CPU/1"),
+                ]
+            }),
+            power_monitor_readings: Box::new(|ids| {
+                (!ids.contains(&0)).then(|| {
+                    let ids = ids.iter().map(|&id| 1000 + id as i64);
+                    (ids.clone().collect(), ids.map(|id| id * 10).collect())
+                })
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+        let stats = Arc::new(stats);
+
+        stats.refresh_async().await.unwrap();
+        assert_eq!(stats.energy_consumers().unwrap()[0].ordinal, 1);
+
+        let readings = stats.read_energy_meters_async(&[2, 1, 2]).await.unwrap();
+        assert_eq!(
+            readings.iter().map(|r| r.energy_uws).collect::<Vec<_>>(),
+            [10020, 10010, 10020]
+        );
+        let readings = stats.read_energy_consumers_async(&[1]).await.unwrap();
+        assert_eq!(readings[0].timestamp, Duration::from_millis(1001));
+
+        let pending = time::timeout(
+            Duration::from_millis(10),
+            stats.read_energy_meters_async(&[0]),
+        );
+        assert!(pending.await.is_err());
+        // Not held up by the abandoned read
+        stats.read_energy_meters_async(&[1]).await.unwrap();
+        assert_eq!(calls.lock().unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn dropped_receivers_async() {
+        let (stats, _) = MockPowerStatsService {
+            drop_receivers: true,
+            ..Default::default()
+        }
+        .into_power_stats();
+        let stats = Arc::new(stats);
+        stats.refresh_async().await.unwrap_err();
+        stats.read_energy_meters_async(&[0]).await.unwrap_err();
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Deref,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use crate::PowerStats;
+
+/// Builds a time series of the power of every UID that a fixed set of consumers attributes
+/// energy to, over repeated [`Self::sample()`] calls, i.e. to plot app power over time.  The power
+/// of a UID in a sample is summed over all consumers that attribute to it.
+///
+/// A UID only gets a point once two consecutive readings of the same consumer attribute to it:
+/// a UID that first appears establishes its baseline, and one that disappears from a consumer
+/// (or whose energy went backwards) has to establish a new baseline for it.  Points older than
+/// the retention window (relative to the latest reading) are dropped, as are UIDs without any
+/// points left.
+#[derive(Debug)]
+pub struct UidPowerTimeline<S: Deref<Target = PowerStats>> {
+    stats: S,
+    ids: Vec<i32>,
+    retention: Duration,
+    /// Timestamp and energy per UID of the previous reading of every consumer, in the order of
+    /// `ids`
+    previous: Option<Vec<(Duration, HashMap<i32, i64>)>>,
+    series: HashMap<i32, VecDeque<(Duration, f64)>>,
+}
+
+impl<S: Deref<Target = PowerStats>> UidPowerTimeline<S> {
+    /// Samples the consumers in `consumer_ids`, keeping `retention` worth of points
+    pub fn new(stats: S, consumer_ids: &[i32], retention: Duration) -> Self {
+        Self {
+            stats,
+            ids: consumer_ids.to_vec(),
+            retention,
+            previous: None,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Reads all consumers and appends a point to the series of every UID whose power is known,
+    /// at the timestamp of the latest reading.  The first call only establishes a baseline.
+    pub fn sample(&mut self) -> Result<()> {
+        let readings = self.stats.read_energy_consumers(&self.ids)?;
+        let current = readings
+            .iter()
+            .map(|r| {
+                let energy = r
+                    .merged_attribution()
+                    .into_iter()
+                    .map(|a| (a.uid, a.energy_uws))
+                    .collect::<HashMap<_, _>>();
+                (r.timestamp, energy)
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(previous) = &self.previous {
+            let mut watts = HashMap::<i32, f64>::new();
+            for ((start, earlier), (end, later)) in previous.iter().zip(&current) {
+                let Some(elapsed) = end.checked_sub(*start).filter(|e| !e.is_zero()) else {
+                    continue;
+                };
+                for (uid, &later) in later {
+                    let Some(&earlier) = earlier.get(uid) else {
+                        continue;
+                    };
+                    if later >= earlier {
+                        *watts.entry(*uid).or_default() +=
+                            (later - earlier) as f64 / 1_000_000.0 / elapsed.as_secs_f64();
+                    }
+                }
+            }
+
+            let Some(&(latest, _)) = current.iter().max_by_key(|(t, _)| *t) else {
+                return Ok(());
+            };
+            for (uid, watts) in watts {
+                self.series
+                    .entry(uid)
+                    .or_default()
+                    .push_back((latest, watts));
+            }
+            let cutoff = latest.saturating_sub(self.retention);
+            self.series.retain(|_, series| {
+                while series.front().is_some_and(|&(t, _)| t < cutoff) {
+                    series.pop_front();
+                }
+                !series.is_empty()
+            });
+        }
+
+        self.previous = Some(current);
+        Ok(())
+    }
+
+    /// Timestamps and average power in watts of `uid` since the previous point, oldest first.
+    /// Empty for UIDs without any retained points.
+    pub fn series(&self, uid: i32) -> Vec<(Duration, f64)> {
+        self.series
+            .get(&uid)
+            .map_or_else(Vec::new, |s| s.iter().copied().collect())
+    }
+
+    /// All UIDs with retained points, sorted
+    pub fn uids(&self) -> Vec<i32> {
+        let mut uids = self.series.keys().copied().collect::<Vec<_>>();
+        uids.sort_unstable();
+        uids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use super::*;
+    use crate::mock::*;
+
+    #[test]
+    fn two_points() {
+        // Over 100ms reads, UID 10123 uses 0.5W and then 1.5W, while UID 1000 only shows up in the
+        // last read and UID 10456 disappears after the first
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            energy_consumed: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                let attribution: &[(i32, i64)] = match t {
+                    0 => &[(10123, 0), (10456, 300)],
+                    1 => &[(10123, 50_000)],
+                    _ => &[(10123, 200_000), (1000, 70_000)],
+                };
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000 + 100 * t, 0, attribution))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut timeline = UidPowerTimeline::new(&stats, &[0], Duration::from_secs(60));
+        for _ in 0..3 {
+            timeline.sample().unwrap();
+        }
+
+        let series = timeline.series(10123);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].0, Duration::from_millis(1100));
+        assert!((series[0].1 - 0.5).abs() < 1e-9);
+        assert_eq!(series[1].0, Duration::from_millis(1200));
+        assert!((series[1].1 - 1.5).abs() < 1e-9);
+        assert_eq!(timeline.uids(), [10123]);
+        assert!(timeline.series(1000).is_empty());
+    }
+
+    #[test]
+    fn retention() {
+        // 1W over 1s reads
+        let tick = AtomicI64::new(0);
+        let (stats, _) = MockPowerStats {
+            energy_consumed: Box::new(move |ids| {
+                let t = tick.fetch_add(1, Ordering::Relaxed);
+                Ok(ids
+                    .iter()
+                    .map(|&id| consumer_result(id, 1000 * t, 0, &[(10123, 1_000_000 * t)]))
+                    .collect())
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let mut timeline = UidPowerTimeline::new(&stats, &[0], Duration::from_secs(2));
+        for _ in 0..6 {
+            timeline.sample().unwrap();
+        }
+        assert_eq!(
+            timeline.series(10123),
+            [
+                (Duration::from_secs(3), 1.0),
+                (Duration::from_secs(4), 1.0),
+                (Duration::from_secs(5), 1.0)
+            ]
+        );
+    }
+}
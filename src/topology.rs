@@ -0,0 +1,116 @@
+use anyhow::Result;
+
+use crate::{EnergyConsumer, EnergyConsumerType, EnergyMeter, PowerStats};
+
+/// Snapshot of all meters and consumers discovered on a device, to pass around or analyze without
+/// holding on to (or repeatedly querying) the service
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceTopology {
+    pub meters: Vec<EnergyMeter>,
+    pub consumers: Vec<EnergyConsumer>,
+}
+
+impl DeviceTopology {
+    pub fn meter(&self, id: i32) -> Option<&EnergyMeter> {
+        self.meters.iter().find(|m| m.id == id)
+    }
+
+    pub fn meter_by_name(&self, name: &str) -> Option<&EnergyMeter> {
+        self.meters.iter().find(|m| m.name == name)
+    }
+
+    pub fn meters_in_subsystem<'a>(
+        &'a self,
+        subsystem: &'a str,
+    ) -> impl Iterator<Item = &'a EnergyMeter> + 'a {
+        self.meters.iter().filter(move |m| m.subsystem == subsystem)
+    }
+
+    pub fn consumer(&self, id: i32) -> Option<&EnergyConsumer> {
+        self.consumers.iter().find(|c| c.id == id)
+    }
+
+    /// Consumers such as CPU clusters share a name, and are distinguished by their ordinal
+    pub fn consumer_by_name(&self, name: &str, ordinal: i32) -> Option<&EnergyConsumer> {
+        self.consumers
+            .iter()
+            .find(|c| c.name == name && c.ordinal == ordinal)
+    }
+
+    pub fn consumers_of_type(
+        &self,
+        r#type: EnergyConsumerType,
+    ) -> impl Iterator<Item = &EnergyConsumer> {
+        self.consumers.iter().filter(move |c| c.r#type == r#type)
+    }
+}
+
+impl TryFrom<&PowerStats> for DeviceTopology {
+    type Error = anyhow::Error;
+
+    fn try_from(stats: &PowerStats) -> Result<Self> {
+        stats.topology()
+    }
+}
+
+impl PowerStats {
+    /// Discovers all meters and consumers
+    pub fn topology(&self) -> Result<DeviceTopology> {
+        Ok(DeviceTopology {
+            meters: self.energy_meters()?,
+            consumers: self.energy_consumers()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{android_hardware_power_stats::EnergyConsumerType as HalType, mock::*};
+
+    #[test]
+    fn lookups() {
+        let (stats, _) = MockPowerStats {
+            energy_meter_info: Box::new(|| {
+                Ok(vec![
+                    channel(0, "S9S_VDD_G3D", "GPU"),
+                    channel(1, "S2M_VDD_CPUCL2", "CPU(BIG)"),
+                    channel(2, "L2S_VDD_G3D_L2", "GPU"),
+                ])
+            }),
+            energy_consumer_info: Box::new(|| {
+                Ok(vec![
+                    consumer(10, 0, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(11, 1, HalType::CPU_CLUSTER, "CPU"),
+                    consumer(12, 0, HalType::OTHER, "GPU"),
+                ])
+            }),
+            ..Default::default()
+        }
+        .into_power_stats();
+
+        let topology = DeviceTopology::try_from(&stats).unwrap();
+        assert_eq!(topology.meters, stats.energy_meters().unwrap());
+        assert_eq!(topology.consumers, stats.energy_consumers().unwrap());
+
+        assert_eq!(topology.meter(1).unwrap().name, "S2M_VDD_CPUCL2");
+        assert_eq!(topology.meter_by_name("L2S_VDD_G3D_L2").unwrap().id, 2);
+        assert_eq!(
+            topology
+                .meters_in_subsystem("GPU")
+                .map(|m| m.id)
+                .collect::<Vec<_>>(),
+            [0, 2]
+        );
+        assert_eq!(topology.consumer(12).unwrap().name, "GPU");
+        assert_eq!(topology.consumer_by_name("CPU", 1).unwrap().id, 11);
+        assert_eq!(
+            topology
+                .consumers_of_type(EnergyConsumerType::CpuCluster)
+                .count(),
+            2
+        );
+        assert!(topology.meter(3).is_none());
+    }
+}